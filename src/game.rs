@@ -12,7 +12,7 @@ use bevy_mod_raycast::prelude::*;
 use bevy_panorbit_camera::*;
 use bevy_rts_camera::*;
 use crate::my_ui::MyUiPlugin;
-use crate::tube_segment::TubeSegmentPlugin;
+use crate::tube_segment::{update_slide_hover, SlideHover, TubeSegmentPlugin};
 use crate::fps::FpsPlugin;
 
 pub struct GamePlugin;
@@ -27,16 +27,22 @@ impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app
             .add_plugins((
-                DefaultPlugins.set(WindowPlugin {
-                    primary_window: Some(Window {
-                        resolution: WindowResolution::new(1500., 600.),
-                        position: WindowPosition::At(IVec2::ZERO),
+                DefaultPlugins
+                    .set(WindowPlugin {
+                        primary_window: Some(Window {
+                            resolution: WindowResolution::new(1500., 600.),
+                            position: WindowPosition::At(IVec2::ZERO),
+                            ..default()
+                        }),
+                        ..default()
+                    })
+                    //hot reload of profiles from assets/profiles
+                    .set(AssetPlugin {
+                        watch_for_changes_override: Some(true),
                         ..default()
                     }),
-                    ..default()
-                }),
                 PanOrbitCameraPlugin,
-                // TubeSegmentPlugin,
+                TubeSegmentPlugin,
                 MyUiPlugin,
                 FpsPlugin,
             ))
@@ -46,16 +52,16 @@ impl Plugin for GamePlugin {
                     setup,
                     setup_cursor,
                     // draw_quad
-                    // setup_control_points_plane
+                    setup_control_points_plane
                 ),
             )
             .add_systems(
                 Update,
                 (
-                    draw_cursor,
+                    //cursor is drawn where the slide is hovered this frame
+                    draw_cursor.after(update_slide_hover),
                     // check_quad_normals_system
                     draw_zero_point_gizmos,
-                    rotate_control_points_plane,
                 ),
             );
     }
@@ -183,6 +189,7 @@ fn draw_cursor(
                 Without<Cursor>
             )
         >,
+    slide_hover: Res<SlideHover>,
     mut raycast: Raycast,
     mut gizmos: Gizmos,
 ) {
@@ -206,6 +213,17 @@ fn draw_cursor(
         false => Vec3::ZERO,
     };
 
+    //slides take the cursor before the ground, it goes onto the path under it
+    let point = match slide_hover.hit {
+        Some(hit) => {
+            let frame = hit.frame;
+            gizmos.arrow(frame.pos, frame.pos + frame.rot * Vec3::Z * 2., ORANGE);
+            gizmos.arrow(frame.pos, frame.pos + frame.up(), LIME);
+            frame.pos
+        }
+        None => point,
+    };
+
     for mut cursor_trm in cursor_transforms.iter_mut() {
         cursor_trm.translation = point;
     }
@@ -262,14 +280,14 @@ fn setup_control_points_plane(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ){
-    // Transparent plane to move control points on
+    // Transparent plane control points are dragged on, shown only while dragging
     commands.spawn((
         PbrBundle{
             mesh: meshes.add(Plane3d::default().mesh().size(40., 40.)),
             material: materials.add(
                 Color::srgba(0., 0., 1., 0.2),
             ),
-            // visibility: Visibility::Hidden,
+            visibility: Visibility::Hidden,
             ..default()
         },
         NotShadowCaster,
@@ -308,27 +326,3 @@ fn draw_zero_point_gizmos(mut gizmos: Gizmos, windows: Query<&Window>) {
     gizmos.arrow(Vec3::ZERO, Vec3::Y * length, GREEN);
     gizmos.arrow(Vec3::ZERO, Vec3::Z * length, BLUE);
 }
-
-fn rotate_control_points_plane(
-    mut transforms: ParamSet<(
-        Query<&mut Transform, With<ControlPointsPlane>>,            //plane trm
-        Query<&Transform, (With<Camera3d>, With<PanOrbitCamera>)>,  //cam trm
-    )>,
-    // mut gizmos: Gizmos,
-) { 
-    let _plane_pos = Vec3::ZERO;
-    let _plane_size = Vec2::splat(40.);
-
-    let mut temp_trm= Transform::default();
-
-    if let Ok(cam_trm) = transforms.p1().get_single() {
-        temp_trm = cam_trm.clone();
-    }
-
-    if let Ok(mut plane_trm) = transforms.p0().get_single_mut() {
-        plane_trm.look_to(
-            temp_trm.local_y(), 
-            Vec3::Y
-        );
-    }
-}
@@ -3,11 +3,39 @@ mod tube_segment;
 mod my_ui;
 mod fps;
 
+use std::path::Path;
 use bevy::prelude::*;
 
 fn main() {
     // std::env::set_var("RUST_BACKTRACE", "1");
 
+    //headless export of a park, or of the default slide without --park. no window:
+    //water_slides --export slides.glb --park parks/park.ron
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|a| a == "--export") {
+        let usage = "usage: water_slides --export <file.glb | file.obj | file.stl> [--park <park.ron>]";
+        let Some(path) = args.get(i + 1) else {
+            eprintln!("{usage}");
+            std::process::exit(2);
+        };
+        let park = match args.iter().position(|a| a == "--park") {
+            Some(j) => match args.get(j + 1) {
+                Some(park) => Some(Path::new(park)),
+                None => {
+                    eprintln!("{usage}");
+                    std::process::exit(2);
+                }
+            },
+            None => None,
+        };
+        if let Err(e) = tube_segment::export_headless(Path::new(path), park) {
+            eprintln!("export failed: {e}");
+            std::process::exit(1);
+        }
+        println!("exported to {path}");
+        return;
+    }
+
     App::new()
         .add_plugins((
             game::GamePlugin, 
@@ -1,5 +1,6 @@
 use bevy::prelude::*;
 use bevy_egui::*;
+use serde::{Deserialize, Serialize};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
 pub struct MyUiPlugin;
@@ -7,7 +8,8 @@ pub struct MyUiPlugin;
 impl Plugin for MyUiPlugin {
     fn build(&self, app: &mut App) {
         app
-            .insert_resource(UiState { t_value: 0., sections_amnt: 8 })
+            .init_resource::<UiState>()
+            .register_type::<UiState>()
             .add_plugins(WorldInspectorPlugin::new())
             //conflicts with inspector
             // .add_plugins(EguiPlugin)
@@ -15,16 +17,130 @@ impl Plugin for MyUiPlugin {
     }
 }
 
-#[derive(Debug, Default, Resource)]
+#[derive(Debug, Clone, PartialEq, Resource, Reflect)]
+#[reflect(Resource)]
 pub struct UiState {
     pub t_value: f32,
-    pub sections_amnt: i32
+    pub sections_amnt: i32,
+    pub ring_spacing: RingSpacing,
+    //0 builds a single surface, more builds inner and outer walls with caps on the slide ends
+    pub wall_thickness: f32,
+    //world units one repeat of slide texture covers
+    pub uv_tile_size: f32,
+    //how far adaptive rings let the tube go from the curve it should follow, world units
+    pub curve_tolerance: f32,
+    //camera distance at which segments switch to lower detail, every next level is twice as far
+    pub lod_distance: f32,
+    pub drag_mode: DragMode,
+    pub snap: SnapSettings,
+}
+
+//also used by headless export, which has no ui
+impl Default for UiState {
+    fn default() -> Self {
+        Self {
+            t_value: 0.,
+            sections_amnt: 8,
+            ring_spacing: RingSpacing::ArcLength,
+            wall_thickness: 0.15,
+            uv_tile_size: 4.,
+            curve_tolerance: 0.02,
+            lod_distance: 40.,
+            drag_mode: DragMode::View,
+            snap: SnapSettings::default(),
+        }
+    }
+}
+
+//snapping of dragged control points
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub struct SnapSettings {
+    //x and z to multiples of grid_size, meters
+    pub grid: bool,
+    pub grid_size: f32,
+    //y to multiples of height_step, meters
+    pub height: bool,
+    pub height_step: f32,
+    //direction of a tangent handle from its end point, angle_step in degrees
+    pub angle: bool,
+    pub angle_step: f32,
+    //free end of a slide sticks to a free end of another slide closer than magnet_radius
+    pub magnet: bool,
+    pub magnet_radius: f32,
+}
+
+impl Default for SnapSettings {
+    fn default() -> Self {
+        Self {
+            grid: false,
+            grid_size: 1.,
+            height: false,
+            height_step: 0.5,
+            angle: false,
+            angle_step: 15.,
+            magnet: true,
+            magnet_radius: 2.,
+        }
+    }
+}
+
+//how tube rings are placed along a segment
+#[derive(Debug, Default, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+pub enum RingSpacing {
+    //uniform curve t, rings bunch up near control points
+    Parametric,
+    //uniform distance along the curve
+    #[default]
+    ArcLength,
+    //by curvature and twist, see curve_tolerance
+    Adaptive,
+}
+
+//what a dragged control point moves along
+#[derive(Debug, Default, Clone, Copy, PartialEq, Reflect)]
+pub enum DragMode {
+    //plane facing the camera
+    #[default]
+    View,
+    //horizontal plane, height stays
+    Ground,
+    //upright plane facing the camera
+    Vertical,
+    AxisX,
+    AxisY,
+    AxisZ,
+}
+
+impl DragMode {
+    pub const ALL: [DragMode; 6] = [
+        DragMode::View,
+        DragMode::Ground,
+        DragMode::Vertical,
+        DragMode::AxisX,
+        DragMode::AxisY,
+        DragMode::AxisZ,
+    ];
+
+    //with the hotkey
+    pub fn name(&self) -> &'static str {
+        match self {
+            DragMode::View => "view (G)",
+            DragMode::Ground => "ground (H)",
+            DragMode::Vertical => "vertical (V)",
+            DragMode::AxisX => "X",
+            DragMode::AxisY => "Y",
+            DragMode::AxisZ => "Z",
+        }
+    }
 }
 
 fn read_slider_value(
     mut contexts: EguiContexts,
-    mut ui_state: ResMut<UiState>,
+    mut ui_state_res: ResMut<UiState>,
 ) {
+    //edited on a copy, meshes are rebuilt when settings really change
+    let mut ui_state = ui_state_res.clone();
+
     egui::Window::new("Hello").show(
         contexts.ctx_mut(), 
         |ui| {
@@ -32,13 +148,52 @@ fn read_slider_value(
                 .text("t value"));
             ui.add(egui::Slider::new(&mut ui_state.sections_amnt, 2..=120)
                 .text("Sections amnt"));
-            ui.separator();
-            // ui.add(egui::Label::new("CP1 pos:"));
-            // ui.add(egui::Label::new("x:"));
-            // ui.add(egui::Label::new("y:"));
-            // ui.add(egui::Label::new("z:"));
-            
+            ui.horizontal(|ui| {
+                ui.label("Rings spacing:");
+                ui.radio_value(&mut ui_state.ring_spacing, RingSpacing::ArcLength, "by distance");
+                ui.radio_value(&mut ui_state.ring_spacing, RingSpacing::Parametric, "by t");
+                ui.radio_value(&mut ui_state.ring_spacing, RingSpacing::Adaptive, "by curvature");
+            });
+            if ui_state.ring_spacing == RingSpacing::Adaptive {
+                ui.add(egui::Slider::new(&mut ui_state.curve_tolerance, 0.001..=0.5)
+                    .logarithmic(true)
+                    .text("Curve tolerance"));
+            }
+            ui.add(egui::Slider::new(&mut ui_state.lod_distance, 5.0..=200.0)
+                .text("LOD distance"));
+            ui.add(egui::Slider::new(&mut ui_state.wall_thickness, 0.0..=1.0)
+                .text("Wall thickness"));
+            ui.add(egui::Slider::new(&mut ui_state.uv_tile_size, 0.1..=20.0)
+                .text("Texture tile size"));
+            ui.horizontal(|ui| {
+                ui.label("Drag:");
+                for mode in DragMode::ALL {
+                    ui.radio_value(&mut ui_state.drag_mode, mode, mode.name());
+                }
+            });
+            ui.collapsing("Snapping", |ui| {
+                let snap = &mut ui_state.snap;
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut snap.grid, "Grid");
+                    ui.add(egui::DragValue::new(&mut snap.grid_size).speed(0.1).range(0.1..=50.).suffix(" m"));
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut snap.height, "Height");
+                    ui.add(egui::DragValue::new(&mut snap.height_step).speed(0.1).range(0.1..=50.).suffix(" m"));
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut snap.angle, "Tangent angle");
+                    ui.add(egui::DragValue::new(&mut snap.angle_step).speed(1.).range(1.0..=90.).suffix("°"));
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut snap.magnet, "Slide ends");
+                    ui.add(egui::DragValue::new(&mut snap.magnet_radius).speed(0.1).range(0.1..=20.).suffix(" m"));
+                });
+            });
+            //values of the selected control point are in the properties window
         }
     );
+
+    ui_state_res.set_if_neq(ui_state);
 }
                     
@@ -1,97 +1,329 @@
+mod arc_length;
+mod curve_kind;
+mod curve_query;
+mod drag;
+mod export;
+mod history;
 mod oriented_point;
+mod park;
+mod profile_asset;
 mod profile_shape;
+mod properties;
+mod selection;
+mod slide;
+mod slide_edit;
+mod snap;
+mod tangent_gizmo;
+mod tube_mesh;
 
 use core::str;
-use std::ops::DerefMut;
+use std::{ops::DerefMut, path::Path};
 use bevy::{
-    color::palettes::css::{AQUA, YELLOW}, 
+    color::palettes::css::YELLOW, 
     prelude::*, 
-    render::{
-        mesh::{
-            Indices, PrimitiveTopology
-        }, 
-        render_asset::RenderAssetUsages
-    }
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
 };
-use bevy_mod_raycast::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use bevy_panorbit_camera::PanOrbitCamera;
+use arc_length::ArcLengthTable;
+use curve_kind::{CurveInput, CurveKind, SegmentCurve};
+use drag::{drag_mode_hotkeys, ActiveDrag, DragConstraint};
+use export::{ExportFormat, ExportSlides, EXPORT_DIR};
+use history::*;
 use my_ui::*;
-use oriented_point::OrientedPoint;
+use oriented_point::{rotation_minimizing_up, OrientedPoint};
+use park::{LoadPark, SavePark, PARKS_DIR};
+use profile_asset::*;
 use profile_shape::*;
+use slide::*;
+use slide_edit::{delete_point_hotkey, ExtendSlide};
+use properties::properties_ui;
+use selection::{curve_color, tint_selection, update_selection, Selection};
+use snap::SnapTargets;
+use tangent_gizmo::{draw_tangent_handles, tangent_rings, TangentGizmo};
+use tube_mesh::{SegmentMeshInput, SegmentMeshes, LOD_COUNT};
 use crate::{game::{ControlPointsPlane, Cursor}, my_ui};
 
+pub use curve_query::{update_slide_hover, CurveHit, SlideHover};
+pub use export::export_headless;
+
 pub struct TubeSegmentPlugin;
 
 impl Plugin for TubeSegmentPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup);
+        app
+            .init_asset::<ProfileShape>()
+            .init_asset_loader::<ProfileShapeLoader>()
+            //shown in the world inspector, field types are registered with them
+            .register_asset_reflect::<ProfileShape>()
+            .register_type::<Slide>()
+            .register_type::<RoadSegment>()
+            .register_type::<ControlPointDraggable>()
+            .init_resource::<History>()
+            .init_resource::<Selection>()
+            .init_resource::<SlideHover>()
+            .init_resource::<TangentGizmo>();
+        app.add_systems(Startup, (setup, load_profile_library));
         app.add_systems(
             Update,
                 // update_road_segment_pts,
                 (
-                    update_states, 
+                    update_slide_hover,
+                    tangent_rings,
+                    update_selection, 
+                    //before anything moves, so a drag step starts where points were pressed
+                    record_drags,
                     update_positions, 
+                    place_tangent_handles,
+                    update_curves,
                     // draw_spline,
                     draw_curve_using_road_segment,
+                    update_slide_frames,
                     draw_profile,
                     generate_mesh,
+                    apply_mesh_tasks,
+                    select_lods,
                 ).chain()
         );
+        app.add_systems(Update, (segments_ui, properties_ui, reload_profiles));
+        app.add_systems(Update, (history_hotkeys, history_ui, despawn_history_garbage));
+        app.add_systems(Update, (drag_mode_hotkeys, delete_point_hotkey, tint_selection, draw_tangent_handles));
     }
 }
 
-#[derive(Component)]
-struct RoadSegment {
-    curve: CubicBezier<Vec3>,
+#[derive(Component, Clone, Reflect)]
+#[reflect(Component)]
+pub struct RoadSegment {
+    curve: SegmentCurve,
+    curve_kind: CurveKind,
+    //nurbs weights of the control points
+    weights: [f32; 4],
+    //start, start tangent handle, end tangent handle, end.
+    //start is shared with the previous segment of the slide, end with the next one
     pts_ids: [Entity; 4],
-    start_pt_id: Option<Entity>,
-    end_pt_id: Option<Entity>,
+    slide: Entity,
+    //up of the profile at the start, carried from the end of the previous segment
+    start_up: Vec3,
+    //distance <-> t, rebuilt with the curve
+    arc_lengths: ArcLengthTable,
+    //distance from the start of the slide to the start of this segment, textures continue from it
+    start_distance: f32,
+    //banks of the start and the end points
+    banks: [f32; 2],
+    profile: ProfileKind,
+    //profile blends into this one towards the end, None keeps the start one
+    end_profile: Option<ProfileKind>,
+    //how far the profile is blended into end_profile at the start and at the end, 0 is profile and 1 end_profile.
+    //halves of a split segment keep their part of the blend
+    blend: [f32; 2],
+    //profile scale at the start and at the end
+    radius: [f32; 2],
+    //lengths of the start and the end bezier handles of this segment only.
+    //None takes the tangent length of the end point, which the neighbour segment shares
+    handle_lens: [Option<f32>; 2],
+    //child with the inner surface, it has its own material
+    inner_wall: Entity,
 }
 
 impl Default for RoadSegment {
     fn default() -> Self {
         Self{
-            curve: CubicBezier::new([[Vec3::INFINITY, Vec3::INFINITY, Vec3::INFINITY, Vec3::INFINITY]]),
+            curve: SegmentCurve::default(),
+            curve_kind: CurveKind::Bezier,
+            weights: [1.; 4],
             pts_ids: [Entity::from_bits(0); 4],
-            start_pt_id: None,
-            end_pt_id: None 
+            slide: Entity::PLACEHOLDER,
+            start_up: Vec3::Y,
+            arc_lengths: ArcLengthTable::default(),
+            start_distance: 0.,
+            banks: [0.; 2],
+            profile: ProfileKind::default(),
+            end_profile: None,
+            blend: [0., 1.],
+            radius: [1.; 2],
+            handle_lens: [None; 2],
+            inner_wall: Entity::PLACEHOLDER,
         }
     }
 }
 
 impl RoadSegment {
-    fn transforms_to_positions(&self, transforms: &Query<&Transform>) -> Vec<Vec3> {
-        let points: Vec<Vec3> = self.pts_ids
-            .iter()
-            .map(|pt_id| transforms.get(*pt_id).unwrap().translation)
-            .collect();
+    fn start_pt_id(&self) -> Entity {
+        self.pts_ids[0]
+    }
+
+    fn end_pt_id(&self) -> Entity {
+        self.pts_ids[3]
+    }
+
+    //transforms of the control points and tangent lengths of the end points
+    fn control_points(&self, transforms: &Query<&Transform>, control_pts: &Query<&ControlPointDraggable>) -> ControlPoints {
+        ControlPoints {
+            trms: self.pts_ids.map(|pt_id| *transforms.get(pt_id).unwrap()),
+            tangent_lens: [0, 3].map(|i| control_pts.get(self.pts_ids[i]).map_or(1., |cp| cp.tangent_len)),
+        }
+    }
+
+    //end point a tangent handle belongs to, and which way the handle points along the forward of that end point
+    fn handle_anchor(&self, handle: Entity) -> Option<(Entity, f32)> {
+        if handle == self.pts_ids[1] {
+            Some((self.pts_ids[0], 1.))
+        } else if handle == self.pts_ids[2] {
+            Some((self.pts_ids[3], -1.))
+        } else {
+            None
+        }
+    }
+
+    fn is_end_point(&self, pt: Entity) -> bool {
+        pt == self.start_pt_id() || pt == self.end_pt_id()
+    }
+
+    //length of the start (0) or the end (1) handle. tangent_len is the one of that end point
+    fn handle_len(&self, side: usize, tangent_len: f32) -> f32 {
+        self.handle_lens[side].unwrap_or(tangent_len)
+    }
+
+    //for bezier mid points are locked to start and end: handles lie along forward of the end points.
+    //segments sharing an end point share its forward too, so the slide has no kink on the joint.
+    //other curve kinds use mid points as they are
+    fn transforms_to_positions(&self, pts: &ControlPoints) -> [Vec3; 4] {
+        if self.curve_kind.has_free_mid_points() {
+            return pts.trms.map(|trm| trm.translation);
+        }
+
+        let [start, _, _, end] = &pts.trms;
+        [
+            start.translation,
+            start.translation + start.forward() * self.handle_len(0, pts.tangent_lens[0]),
+            end.translation - end.forward() * self.handle_len(1, pts.tangent_lens[1]),
+            end.translation,
+        ]
+    }
+
+    //velocities at the start and at the end, same as bezier handles give
+    fn end_tangents(&self, pts: &ControlPoints) -> [Vec3; 2] {
+        [
+            pts.trms[0].forward() * self.handle_len(0, pts.tangent_lens[0]) * 3.,
+            pts.trms[3].forward() * self.handle_len(1, pts.tangent_lens[1]) * 3.,
+        ]
+    }
+
+    fn curve_input(&self, pts: &ControlPoints) -> CurveInput {
+        CurveInput {
+            kind: self.curve_kind,
+            pts: self.transforms_to_positions(pts),
+            end_tangents: self.end_tangents(pts),
+            weights: self.weights,
+        }
+    }
+
+    fn store_curve(&mut self, input: CurveInput) {
+        self.curve = input.build();
+        self.arc_lengths = ArcLengthTable::new(|t| self.curve.position(t), ArcLengthTable::DEFAULT_SAMPLES);
+    }
+
+    //up at the start continuing up the previous segment ended with, world up for the first segment
+    fn start_up_from(&self, up: Vec3) -> Vec3 {
+        let tangent = self.curve.velocity(0.).normalize_or_zero();
+        (up - tangent * up.dot(tangent))
+            .try_normalize()
+            .unwrap_or_else(|| tangent.any_orthonormal_vector())
+    }
+
+    //up the next segment starts with
+    fn end_up(&self) -> Vec3 {
+        self.get_rmf_oriented_points(&[1.])[0].up()
+    }
+
+    //frames at given t's, t's should go in ascending order.
+    //up is carried from start_up with rotation minimizing frames so profiles do not twist or flip
+    //when the curve goes vertical or turns sharply
+    fn get_rmf_oriented_points(&self, ts: &[f32]) -> Vec<OrientedPoint> {
+        //up is carried over a fixed grid of t's and only the last bit to each t is stepped on its own,
+        //so the frame at a t does not depend on the other t's asked for.
+        //the mesh end ring and end_up then agree and neighbour rings meet exactly
+        const STEPS: usize = 64;
+
+        let curve = &self.curve;
+        let tangent_at = |t: f32, fallback: Vec3| curve.velocity(t).try_normalize().unwrap_or(fallback);
+
+        let mut step = 0;
+        let mut pos = curve.position(0.);
+        let mut tangent = tangent_at(0., Vec3::Z);
+        let mut up = self.start_up;
+
+        ts.iter()
+            .map(|&t| {
+                let t = t.clamp(0., 1.);
+                while ((step + 1) as f32 / STEPS as f32) <= t {
+                    step += 1;
+                    let t_step = step as f32 / STEPS as f32;
+                    let next_pos = curve.position(t_step);
+                    let next_tangent = tangent_at(t_step, tangent);
+                    up = rotation_minimizing_up(pos, tangent, up, next_pos, next_tangent);
+                    pos = next_pos;
+                    tangent = next_tangent;
+                }
 
-        points
+                if t > step as f32 / STEPS as f32 {
+                    let t_pos = curve.position(t);
+                    let t_tangent = tangent_at(t, tangent);
+                    let t_up = rotation_minimizing_up(pos, tangent, up, t_pos, t_tangent);
+                    OrientedPoint::from_forward_up(t_pos, t_tangent, t_up)
+                } else {
+                    OrientedPoint::from_forward_up(pos, tangent, up)
+                }
+            })
+            .collect()
     }
 
-    fn calc_and_store_curve_return_curve_pts(&mut self, positions: &Vec<Vec3>, subdivisions: usize) -> Vec<Vec3> {
-        let positions_as_arr: [Vec3; 4] = positions
-            .as_slice()
-            .try_into()
-            .unwrap();
+    //radius and blended profile at the start (0) or the end (1)
+    fn joint(&self, side: usize) -> Joint {
+        let blend = self.blend[side];
+        let (profile, end_profile, blend) = match &self.end_profile {
+            Some(end) if blend >= 1. => (end.clone(), None, 0.),
+            Some(end) if blend > 0. => (self.profile.clone(), Some(end.clone()), blend),
+            _ => (self.profile.clone(), None, 0.),
+        };
+        Joint { radius: self.radius[side], profile, end_profile, blend }
+    }
 
-        self.curve = CubicBezier::new([positions_as_arr]);
+    //bank eases in and out of the end points so the roll speed is zero on joints
+    fn bank_at(&self, t: f32) -> f32 {
+        self.banks[0] + (self.banks[1] - self.banks[0]) * smoothstep(t)
+    }
 
-        self.curve
-            .to_curve()
-            .iter_positions(subdivisions)
-            .collect::<Vec<Vec3>>()
+    //start and end profiles with the same topology so rings can blend between them.
+    //None while a profile asset is not loaded
+    fn ring_profiles(&self, profiles: &Assets<ProfileShape>) -> Option<(ProfileShape, ProfileShape)> {
+        let start = self.profile.shape(profiles)?;
+        match &self.end_profile {
+            Some(end) => Some(ProfileShape::make_compatible(&start, &end.shape(profiles)?)),
+            None => Some((start.clone(), start)),
+        }
     }
 
-    fn get_bezier_oriented_point(&self, t: f32) -> OrientedPoint {
-        OrientedPoint::from_forward(
-            self.curve.to_curve().position(t), 
-            self.curve.to_curve().velocity(t).normalize()
-        )
+    //blended and scaled profile at t, eases like bank so joints stay smooth
+    fn ring_profile(&self, (start, end): &(ProfileShape, ProfileShape), t: f32) -> ProfileShape {
+        let k = smoothstep(t);
+        let radius = self.radius[0] + (self.radius[1] - self.radius[0]) * k;
+        let blend = self.blend[0] + (self.blend[1] - self.blend[0]) * k;
+        start.lerp(end, blend).scaled(radius)
+    }
+
+    //rotation minimizing frames rolled by the bank of the segment
+    fn get_banked_oriented_points(&self, ts: &[f32]) -> Vec<OrientedPoint> {
+        self.get_rmf_oriented_points(ts)
+            .into_iter()
+            .zip(ts.iter())
+            .map(|(op, &t)| op.rolled(self.bank_at(t)))
+            .collect()
     }
 
     #[allow(dead_code)]
     fn get_profile_center_and_lines(&self, t: f32, profile_shape: &ProfileShape) -> (OrientedPoint, Vec<(Vec3, Vec3)>) {
-        let op = self.get_bezier_oriented_point(t);
+        let op = self.get_banked_oriented_points(&[t])[0];
         let shape_2d = profile_shape;
         
         //lines
@@ -107,35 +339,142 @@ impl RoadSegment {
         (op, line_pairs)
     }
     
-    fn get_approx_len(&self) -> f32 {
-        const PRESCISION: usize = 8;
-        let mut points: Vec<Vec3> = vec![];
-        
-        for i in 0..PRESCISION {
-            let t = i as f32 / (PRESCISION - 1) as f32;
-            points.push(self.get_bezier_oriented_point(t).pos);
-        }
-        
-        let mut dist: f32 = 0.;
-        for i in 0..(PRESCISION - 1) {
-            let a = points[i];
-            let b = points[i + 1];
-            dist += Vec3::distance(a, b);
+    fn get_len(&self) -> f32 {
+        self.arc_lengths.length()
+    }
+
+    //rings spread evenly by t or by distance along the curve
+    fn ring_ts(&self, ring_count: usize, spacing: RingSpacing) -> Vec<f32> {
+        (0..ring_count)
+            .map(|ring| ring as f32 / (ring_count - 1) as f32)
+            .map(|k| match spacing {
+                RingSpacing::Parametric => k,
+                //adaptive rings come from adaptive_ring_ts, evenly spread here
+                RingSpacing::ArcLength | RingSpacing::Adaptive => self.arc_lengths.t_at_fraction(k),
+            })
+            .collect()
+    }
+
+    //rings dense in tight turns and twists, sparse on straights.
+    //a ring is skipped while the tube between its neighbours stays within tolerance (world units) of a straight extrusion.
+    //probe_radius: how far from the curve the tube goes, bank twists the tube more the wider it is
+    fn adaptive_ring_ts(&self, tolerance: f32, probe_radius: f32) -> Vec<f32> {
+        const SAMPLES: usize = 256;
+
+        let ts: Vec<f32> = (0..=SAMPLES).map(|i| self.arc_lengths.t_at_fraction(i as f32 / SAMPLES as f32)).collect();
+        let probes: Vec<[Vec3; 4]> = self.get_banked_oriented_points(&ts)
+            .into_iter()
+            .zip(ts.iter())
+            .map(|(op, &t)| {
+                let r = probe_radius * (self.radius[0] + (self.radius[1] - self.radius[0]) * smoothstep(t));
+                [Vec2::ZERO, Vec2::Y * r, Vec2::X * r, Vec2::NEG_X * r].map(|p| op.local_to_world_pos(p))
+            })
+            .collect();
+
+        //samples are evenly spread by distance, so lerping by index is lerping by distance
+        let fits = |from: usize, to: usize| {
+            (from + 1..to).all(|k| {
+                let f = (k - from) as f32 / (to - from) as f32;
+                (0..4).all(|p| probes[k][p].distance(probes[from][p].lerp(probes[to][p], f)) <= tolerance)
+            })
+        };
+
+        let mut ring_ts = vec![ts[0]];
+        let mut from = 0;
+        let mut to = from + 1;
+        while to < SAMPLES {
+            if fits(from, to + 1) {
+                to += 1;
+            } else {
+                ring_ts.push(ts[to]);
+                from = to;
+                to = from + 1;
+            }
         }
-        
-        dist
+        ring_ts.push(ts[SAMPLES]);
+
+        ring_ts
+    }
+}
+
+//slide the editor starts with, bezier points as SpawnSlide takes them
+fn default_slide_positions() -> Vec<Vec3> {
+    vec![
+        Vec3::new(-10., 0.,  10.),
+        Vec3::new(-10., 0., -10.),
+        Vec3::new( 10., 0., -10.),
+        Vec3::new( 10., 0.,  10.),
+        //next segments, start point and its handle are taken from the end of the previous one.
+        //the handle is written mirrored over the joint like SpawnSlide makes it
+        Vec3::new( 10., 0.,  30.),
+        Vec3::new( 25., 5.,  25.),
+        Vec3::new( 25., 0., -10.),
+    ]
+}
+
+//where a segment ends the next one of the slide starts, with the same radius and profile,
+//so the tube has no step on the joint. only the first segment of a slide has a start of its own
+#[derive(Clone, PartialEq)]
+struct Joint {
+    radius: f32,
+    profile: ProfileKind,
+    //Some when the joint is inside a blend, the next segment goes on blending the same profiles
+    end_profile: Option<ProfileKind>,
+    blend: f32,
+}
+
+impl Joint {
+    //start of the segment continues this joint, its end stays. touches only fields that differ
+    fn apply_to(&self, rs: &mut Mut<RoadSegment>) {
+        if rs.radius[0] != self.radius { rs.radius[0] = self.radius; }
+        if rs.profile != self.profile { rs.profile = self.profile.clone(); }
+        if rs.blend[0] != self.blend { rs.blend[0] = self.blend; }
+        if self.end_profile.is_some() && rs.end_profile != self.end_profile { rs.end_profile = self.end_profile.clone(); }
     }
 }
 
-#[derive(PartialEq)]
+//eases 0..1 in and out, zero speed on both ends
+fn smoothstep(t: f32) -> f32 {
+    let t = t.clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Reflect)]
 enum ControlPointState {
+    #[default]
     None,
     Drag,
 }
 
-#[derive(Component)]
+#[derive(Component, Clone, Reflect)]
+#[reflect(Component)]
 struct ControlPointDraggable {
     pub state: ControlPointState,
+    //roll of the slide profile at this point, radians. positive lifts the left side looking down the slide.
+    //only end points of a segment are used, mid points are handles
+    pub bank: f32,
+    //length of the bezier handles of an end point along its forward. both segments meeting at the point
+    //share it, unless they have their own handle length. unused on handles
+    pub tangent_len: f32,
+}
+
+impl Default for ControlPointDraggable {
+    fn default() -> Self {
+        Self {
+            state: ControlPointState::None,
+            bank: 0.,
+            tangent_len: 1.,
+        }
+    }
+}
+
+//what a segment curve is built from
+#[derive(Clone, Copy)]
+struct ControlPoints {
+    //in pts_ids order
+    trms: [Transform; 4],
+    //tangent lengths of the start and the end point
+    tangent_lens: [f32; 2],
 }
 
 #[derive(Component)]
@@ -144,53 +483,28 @@ struct MovingSphere;
 #[derive(Component)]
 struct CustomMesh;
 
+//inner surface of a segment when the tube has a wall thickness
+#[derive(Component)]
+struct InnerWall;
+
+//segment meshes of every level of detail being built on the async compute pool
+#[derive(Component)]
+struct MeshTask(Task<Vec<SegmentMeshes>>);
+
+//mesh assets of every level of detail, segment and its inner wall show the ones of current level
+#[derive(Component)]
+struct SegmentLods {
+    outer: [Handle<Mesh>; LOD_COUNT],
+    inner: [Handle<Mesh>; LOD_COUNT],
+}
+
 
 fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    asset_server: Res<AssetServer>,
 ) {
-    let positions = [
-        Vec3::new(-10., 0.,  10.),
-        Vec3::new(-10., 0., -10.),
-        Vec3::new( 10., 0., -10.),
-        Vec3::new( 10., 0.,  10.),
-    ];
-
-    //control points
-    // let control_pts_ids: [Entity; 4] = positions.map(|p|{
-    //     commands.spawn((
-    //         PbrBundle {
-    //             mesh: meshes.add(Sphere::new(1.)),
-    //             material: materials.add(Color::srgba(1., 1., 1., 0.2)),
-    //             transform: Transform::from_translation(p),
-    //             ..default()
-    //         },
-    //         ControlPointDraggable {
-    //             state: ControlPointState::None,
-    //         },
-    //     ))
-    //     .id()
-    // });
-
-    let mut control_pts_ids: [Entity; 4] = [Entity::PLACEHOLDER; 4];
-
-    for i in 0..positions.len() {
-        control_pts_ids[i] = commands.spawn((
-            Name::new(format!("Control Point {i}")),
-            PbrBundle {
-                mesh: meshes.add(Sphere::new(1.)),
-                material: materials.add(Color::srgba(1., 1., 1., 0.2)),
-                transform: Transform::from_translation(positions[i]),
-                ..default()
-            },
-            ControlPointDraggable {
-                state: ControlPointState::None,
-            },
-        ))
-        .id()
-    }
+    let positions = default_slide_positions();
 
     //moving sphere
     commands.spawn((
@@ -203,135 +517,84 @@ fn setup(
         MovingSphere
     ));
 
-
-    //generated mesh
-
-    let texture_handle: Handle<Image> = asset_server.load("textures/uv_mapper.png");
-    let mesh_handle: Handle<Mesh> = meshes.add(
-        Mesh::new(
-            PrimitiveTopology::TriangleList, 
-            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD
-        )
-        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vec![
-            Vec3::new(-1.,  1., 0.),
-            Vec3::new( 1.,  1., 0.),
-            Vec3::new( 1., -1., 0.),
-            Vec3::new(-1., -1., 0.),
-        ])
-        .with_inserted_indices(Indices::U32(vec![0, 3, 1, 1, 3, 2]))
-        .with_computed_normals()
-    );
-
-    // Render the mesh with the custom texture using a PbrBundle, add the marker.
-    commands.spawn((
-        PbrBundle {
-            mesh: mesh_handle.clone(),
-            material: materials.add(StandardMaterial {
-                // base_color_texture: Some(texture_handle),
-                base_color: Color::Srgba(AQUA), 
-                ..default()
-            }),
-            ..default()
-        },
-        CustomMesh,
-    ));
-
-    //road segment
-    commands
-        .spawn((
-                SpatialBundle::default(),
-                RoadSegment {
-                    curve: CubicBezier::new([positions]),
-                    pts_ids: control_pts_ids,
-                    start_pt_id: Some(control_pts_ids[0]),
-                    end_pt_id: Some(control_pts_ids[3])
-                }
-        ))
-        .push_children(&control_pts_ids);
+    //slide
+    commands.add(SpawnSlide { positions });
 }
 
-fn update_states(
+//lead point of the selection follows the cursor ray on the plane or the axis of the drag mode,
+//other dragged points move by as much as it does.
+//mode can be switched in the middle of a drag, the new one starts where the point is
+fn update_positions(
     cameras: Query<(&Camera, &GlobalTransform)>,
     windows: Query<&Window>,
-    buttons: Res<ButtonInput<MouseButton>>,
-    mut raycast: Raycast,
-    mut control_points: Query<(&Transform, &mut ControlPointDraggable)>,
-    mut ctrl_pts_plane: Query<
-        &mut Transform, 
-        (With<ControlPointsPlane>, Without<Cursor>, Without<ControlPointDraggable>)
-    >
+    ui_state: Res<UiState>,
+    selection: Res<Selection>,
+    mut active_drag: Local<Option<ActiveDrag>>,
+    mut gizmos: Gizmos,
+    mut cursors: Query<&mut Transform, (With<Cursor>, Without<ControlPointDraggable>)>,
+    mut planes: Query<
+        (&mut Transform, &mut Visibility),
+        (With<ControlPointsPlane>, Without<Cursor>, Without<ControlPointDraggable>),
+    >,
+    mut ctrl_pts_transforms: Query<
+        (Entity, &mut Transform, &ControlPointDraggable), 
+        Without<ControlPointsPlane>,
+    >,
+    slides: Query<&Slide>,
+    road_segments: Query<&RoadSegment>,
 ) {
+    let dragged = selection.lead
+        .and_then(|id| ctrl_pts_transforms.get(id).ok())
+        .filter(|(_, _, cp)| cp.state == ControlPointState::Drag)
+        .map(|(id, trm, _)| (id, trm.translation));
+
+    let Ok((mut plane_trm, mut plane_visibility)) = planes.get_single_mut() else {return;};
+    let Some((id, pos)) = dragged else {
+        *active_drag = None;
+        plane_visibility.set_if_neq(Visibility::Hidden);
+        return;
+    };
+
     let (camera, camera_transform) = cameras.single();
     let Some(cursor_position) = windows.single().cursor_position() else {return; };
     let Some(ray) = camera.viewport_to_world(camera_transform, cursor_position) else {return;};
-    
-    let Ok(mut ctrl_pts_plane_trm) = ctrl_pts_plane.get_single_mut() else {return;};
 
-    let intersections = raycast.cast_ray(
-        ray,
-        &RaycastSettings {
-            filter: &|e| control_points.contains(e),
-            ..default()
-        },
-    );
-    
-    if intersections.len() > 0 {
-        if let Ok((ctrl_pt_trm, mut ctrl_pt_draggable)) 
-        = control_points.get_mut(intersections[0].0) {
-            ctrl_pt_draggable.state = if buttons.pressed(MouseButton::Left) {
-                if ctrl_pt_draggable.state == ControlPointState::None {
-                    ctrl_pts_plane_trm.translation = ctrl_pt_trm.translation;
-                }
-                ControlPointState::Drag
-            } else {
-                ControlPointState::None
-            };
-        }
+    let restart = active_drag.as_ref().map_or(true, |d| d.point != id || d.mode != ui_state.drag_mode);
+    if restart {
+        *active_drag = ActiveDrag::start(id, pos, ui_state.drag_mode, ray, camera_transform);
     }
-}
+    let Some(drag) = active_drag.as_ref() else {return;};
 
-//if dragging cp
-//raycast, detect cp
-//clip plane to the cp
-//raycast, detect plane
-//move cp on plane surface
+    //plane guide, axis ones are drawn with gizmos
+    match drag.constraint {
+        DragConstraint::Plane(normal) => {
+            plane_visibility.set_if_neq(Visibility::Inherited);
+            *plane_trm = Transform::from_translation(drag.origin)
+                .with_rotation(Quat::from_rotation_arc(Vec3::Y, *normal));
+        }
+        DragConstraint::Axis(_) => {
+            plane_visibility.set_if_neq(Visibility::Hidden);
+        }
+    }
+    drag.draw_guide(&mut gizmos);
 
-fn update_positions(
-    cameras: Query<(&Camera, &GlobalTransform)>,
-    windows: Query<&Window>,
-    mut raycast: Raycast,
-    mut cursors: Query<&mut Transform, (With<Cursor>, Without<ControlPointDraggable>)>,
-    planes: Query<(&ControlPointsPlane, &Transform), Without<Cursor>>,
-    mut ctrl_pts_transforms: Query<
-        (&mut Transform, &ControlPointDraggable), 
-        Without<ControlPointsPlane>,
-    >,
-) {
-    for (mut ctrl_pt_trm, ctrl_pt_cmp) in ctrl_pts_transforms.iter_mut() {
-        if let ControlPointState::Drag = ctrl_pt_cmp.state {
-            //make cursor move on plane if dragging
+    let Some(target) = drag.target(ray) else {return;};
+    let snap_targets = SnapTargets::find(id, &slides, &road_segments, |e| {
+        ctrl_pts_transforms.get(e).ok().map(|(_, trm, _)| trm.translation)
+    });
+    let target = snap::snap(target, drag.constraint, &ui_state.snap, &snap_targets, &mut gizmos);
 
-            let (camera, camera_transform) = cameras.single();
-            let Some(cursor_position) = windows.single().cursor_position() else {return; };
-            let Some(ray) = camera.viewport_to_world(camera_transform, cursor_position) else {return;};
-            
-            let Ok(mut cursor) = cursors.get_single_mut() else {return;};
-            
-            let intersections = raycast.cast_ray(
-                ray,
-                &RaycastSettings {
-                    filter: &|e| planes.contains(e),
-                    ..default()
-                },
-            );
-
-            if intersections.len() > 0 {
-                //changing cursor position again? this is stupid. should check if ctrl pt draggable maybe?
-                cursor.translation = intersections[0].1.position();
-                ctrl_pt_trm.translation = cursor.translation
+    let delta = target - pos;
+    if delta != Vec3::ZERO {
+        for (_, mut trm, cp) in ctrl_pts_transforms.iter_mut() {
+            if cp.state == ControlPointState::Drag {
+                trm.translation += delta;
             }
         }
     }
+    if let Ok(mut cursor) = cursors.get_single_mut() {
+        cursor.translation = target;
+    }
 }
 
 #[allow(dead_code)]
@@ -353,73 +616,324 @@ fn draw_spline(
 }
 
 
-fn draw_curve_using_road_segment(
-    mut road_segments: Query<&mut RoadSegment>,
-    transforms: Query<&Transform>,    
-    mut gizmos: Gizmos,
+//dragged bezier handle aims the forward of its end point and sets the tangent length
+//(of the end point, or the handle length of its segment when the segment has its own),
+//then every handle sphere follows the tangent of its end point.
+//the end point is shared with the neighbour segment, so its handle on the other side turns too
+fn place_tangent_handles(
+    mut road_segments: Query<(Entity, &mut RoadSegment)>,
+    mut control_pts: Query<&mut ControlPointDraggable>,
+    mut transforms: ParamSet<(
+        Query<&Transform>,
+        Query<&mut Transform, With<ControlPointDraggable>>,
+    )>,
 ) {
-    for mut rs in road_segments.iter_mut() {
-        let positions = rs.transforms_to_positions(&transforms);
+    let is_dragged = |id: Entity| control_pts.get(id).is_ok_and(|cp| cp.state == ControlPointState::Drag);
+    //end point, tangent, segment and side when the length is the segment's own
+    let aims: Vec<(Entity, Vec3, Option<(Entity, usize)>)> = road_segments
+        .iter()
+        .filter(|(_, rs)| !rs.curve_kind.has_free_mid_points())
+        .flat_map(|(id, rs)| [(1, rs.pts_ids[1]), (2, rs.pts_ids[2])].map(|(i, handle)| (id, rs, i, handle)))
+        .filter(|(_, _, _, handle)| is_dragged(*handle))
+        .filter_map(|(id, rs, i, handle)| {
+            let (anchor, sign) = rs.handle_anchor(handle)?;
+            let trms = transforms.p0();
+            let handle_pos = trms.get(handle).ok()?.translation;
+            let anchor_pos = trms.get(anchor).ok()?.translation;
+            let side = i - 1;
+            let own_len = rs.handle_lens[side].map(|_| (id, side));
+            Some((anchor, (handle_pos - anchor_pos) * sign, own_len))
+        })
+        .collect();
 
-        gizmos.linestrip(
-            rs.calc_and_store_curve_return_curve_pts(&positions, 100), 
-            Color::WHITE
-        );
+    {
+        let mut anchor_trms = transforms.p1();
+        for (anchor, tangent, own_len) in aims.iter() {
+            if tangent.length_squared() < f32::EPSILON { continue; }
+            let Ok(mut trm) = anchor_trms.get_mut(*anchor) else { continue; };
+            let aimed = control_point_transform(trm.translation, *tangent);
+            if trm.rotation != aimed.rotation {
+                trm.rotation = aimed.rotation;
+            }
+            if own_len.is_some() { continue; }
+            if let Ok(mut cp) = control_pts.get_mut(*anchor) {
+                cp.map_unchanged(|cp| &mut cp.tangent_len).set_if_neq(tangent.length());
+            }
+        }
+    }
+    for (_, tangent, own_len) in aims {
+        let Some((id, side)) = own_len else { continue; };
+        let Ok((_, mut rs)) = road_segments.get_mut(id) else { continue; };
+        let len = Some(tangent.length());
+        if rs.handle_lens[side] != len {
+            rs.handle_lens[side] = len;
+        }
+    }
+
+    let handles: Vec<(Entity, Vec3)> = road_segments
+        .iter()
+        .map(|(_, rs)| rs)
+        .filter(|rs| !rs.curve_kind.has_free_mid_points())
+        .flat_map(|rs| {
+            let positions = rs.transforms_to_positions(&rs.control_points(&transforms.p0(), &control_pts.to_readonly()));
+            [(rs.pts_ids[1], positions[1]), (rs.pts_ids[2], positions[2])]
+        })
+        .collect();
+
+    let mut handle_trms = transforms.p1();
+    for (id, pos) in handles {
+        if let Ok(mut trm) = handle_trms.get_mut(id) {
+            trm.map_unchanged(|t| &mut t.translation).set_if_neq(pos);
+        }
     }
 }
 
-#[allow(dead_code)]
-fn draw_curve_using_road_segment_other_curve_options(
+//segments of every slide, clicking one selects it. they are edited in the properties window
+fn segments_ui(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    ui_state: Res<UiState>,
+    mut selection: ResMut<Selection>,
+    slides: Query<(Entity, &Slide)>,
+    road_segments: Query<&RoadSegment>,
+    mut park_path: Local<String>,
+) {
+    if park_path.is_empty() {
+        *park_path = format!("{PARKS_DIR}/park.ron");
+    }
+
+    egui::Window::new("Segments").show(
+        contexts.ctx_mut(), 
+        |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Export:");
+                for format in ExportFormat::ALL {
+                    //stl is for printing, open tubes can not be printed
+                    let enabled = format != ExportFormat::Stl || ui_state.wall_thickness > 0.;
+                    if ui.add_enabled(enabled, egui::Button::new(format.name())).clicked() {
+                        commands.add(ExportSlides {
+                            path: Path::new(EXPORT_DIR).join(format!("slides.{}", format.extension())),
+                            format,
+                        });
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Park:");
+                ui.text_edit_singleline(park_path.deref_mut());
+                if ui.button("Save").clicked() {
+                    commands.add(SavePark { path: park_path.clone().into() });
+                }
+                if ui.button("Load").clicked() {
+                    commands.add(LoadPark { path: park_path.clone().into() });
+                }
+            });
+            ui.separator();
+
+            for (slide_id, slide) in slides.iter() {
+                ui.label(format!("Slide {slide_id}"));
+
+                for (i, id) in slide.segments.iter().enumerate() {
+                    let Ok(rs) = road_segments.get(*id) else { continue; };
+                    let profile = match &rs.end_profile {
+                        Some(end) => format!("{} to {}", rs.profile.name(), end.name()),
+                        None => rs.profile.name(),
+                    };
+                    let text = format!("Segment {i}: {}, {profile}", rs.curve_kind.name());
+                    if ui.selectable_label(selection.segments.contains(id), text).clicked() {
+                        selection.clear();
+                        selection.add_segment(*id, rs);
+                    }
+                }
+
+                //points are inserted with ctrl+click on the curve and deleted with delete
+                ui.horizontal(|ui| {
+                    if ui.button("Extend start").clicked() {
+                        commands.add(ExtendSlide { slide: slide_id, at_start: true });
+                    }
+                    if ui.button("Extend end").clicked() {
+                        commands.add(ExtendSlide { slide: slide_id, at_start: false });
+                    }
+                });
+            }
+        }
+    );
+}
+
+//combo with presets and profile assets, plus the params of the picked preset
+//curve kind, profiles and radius of a segment.
+//id_salt keeps combo boxes of different segments apart.
+//start of every segment but the first one of a slide continues the previous one, see Joint
+fn segment_props_ui(
+    ui: &mut egui::Ui,
+    commands: &mut Commands,
+    id_salt: (&str, Entity),
+    props: &mut SegmentProps,
+    is_first: bool,
+    profile_assets: &[(String, Handle<ProfileShape>)],
+) {
+    ui.horizontal(|ui| {
+        ui.label("Curve:");
+        egui::ComboBox::from_id_salt(id_salt)
+            .selected_text(props.curve_kind.name())
+            .show_ui(ui, |ui| {
+                for k in CurveKind::ALL {
+                    ui.selectable_value(&mut props.curve_kind, k, k.name());
+                }
+            });
+
+        //end point weights stay 1, mid ones pull the curve towards their points
+        if props.curve_kind == CurveKind::Nurbs {
+            for w in props.weights[1..3].iter_mut() {
+                ui.add(egui::DragValue::new(w).speed(0.05).range(0.1..=10.));
+            }
+        }
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Profile:");
+        ui.add_enabled_ui(is_first, |ui| {
+            profile_picker(ui, commands, (id_salt, "profile"), &mut props.profile, profile_assets);
+        });
+    });
+
+    ui.horizontal(|ui| {
+        let mut morph = props.end_profile.is_some();
+        //a blend going on from the previous segment can not stop on the joint
+        let continues_blend = !is_first && props.blend[0] > 0.;
+        ui.add_enabled(!continues_blend, egui::Checkbox::new(&mut morph, "Morph to:"));
+        if !morph {
+            props.end_profile = None;
+        } else if let Some(end) = props.end_profile.as_mut() {
+            profile_picker(ui, commands, (id_salt, "end_profile"), end, profile_assets);
+        } else {
+            //start morphing from the current profile, over the whole segment
+            props.end_profile = Some(props.profile.clone());
+            props.blend = [0., 1.];
+        }
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Radius:");
+        for (side, r) in props.radius.iter_mut().enumerate() {
+            ui.add_enabled(is_first || side == 1, egui::DragValue::new(r).speed(0.01).range(0.1..=10.).prefix("x"));
+        }
+    });
+}
+
+//outer and inner wall colors of a segment, every change goes into history
+fn segment_colors_ui(
+    ui: &mut egui::Ui,
+    entities: [Entity; 2],
+    material_handles: &Query<&Handle<StandardMaterial>>,
+    materials: &mut Assets<StandardMaterial>,
+    history: &mut History,
+) {
+    ui.horizontal(|ui| {
+        ui.label("Colors:");
+        for entity in entities {
+            let Ok(handle) = material_handles.get(entity) else { continue; };
+            let Some(before) = materials.get(handle).map(|m| m.base_color) else { continue; };
+            let mut color = before.to_srgba().to_f32_array();
+            //get_mut marks the material changed, so only when the color really changed
+            if ui.color_edit_button_rgba_unmultiplied(&mut color).changed() {
+                let after = Color::srgba(color[0], color[1], color[2], color[3]);
+                if let Some(m) = materials.get_mut(handle) {
+                    m.base_color = after;
+                }
+                history.record(EditorCommand::SetColor { entity, before, after });
+            }
+        }
+    });
+}
+
+fn profile_picker(
+    ui: &mut egui::Ui,
+    commands: &mut Commands,
+    id_salt: impl std::hash::Hash,
+    profile: &mut ProfileKind,
+    profile_assets: &[(String, Handle<ProfileShape>)],
+) {
+    egui::ComboBox::from_id_salt(id_salt)
+        .selected_text(profile.name())
+        .show_ui(ui, |ui| {
+            for preset in ProfileKind::PRESETS {
+                //keep current params when the same kind is picked again
+                if ui.selectable_label(profile.name() == preset.name(), preset.name()).clicked()
+                    && profile.name() != preset.name() {
+                    *profile = preset;
+                }
+            }
+            ui.separator();
+            for (name, handle) in profile_assets.iter() {
+                ui.selectable_value(profile, ProfileKind::Asset(handle.clone()), name.as_str());
+            }
+        });
+
+    match profile {
+        ProfileKind::Circle { sides } => {
+            ui.add(egui::DragValue::new(sides).range(3..=64).prefix("sides: "));
+        }
+        ProfileKind::HalfPipe { sides, opening } => {
+            ui.add(egui::DragValue::new(sides).range(2..=64).prefix("sides: "));
+            ui.add(egui::DragValue::new(opening).range(1.0..=359.).suffix("°"));
+        }
+        ProfileKind::Flume { width, height } => {
+            ui.add(egui::DragValue::new(width).speed(0.05).range(0.1..=20.).prefix("w: "));
+            ui.add(egui::DragValue::new(height).speed(0.05).range(0.1..=20.).prefix("h: "));
+        }
+        ProfileKind::RaftChannel { width, depth } => {
+            ui.add(egui::DragValue::new(width).speed(0.05).range(0.1..=20.).prefix("w: "));
+            ui.add(egui::DragValue::new(depth).speed(0.05).range(0.1..=20.).prefix("d: "));
+        }
+        ProfileKind::Asset(_) => return,
+    }
+
+    //presets are where new profile files start from
+    if ui.small_button("Save").on_hover_text("Save as a profile file in assets/profiles").clicked() {
+        commands.add(SaveProfile { profile: profile.clone() });
+    }
+}
+
+//curves are rebuilt only when what they are built from changed, not on every change of the segment.
+//frames written by update_slide_frames or a new profile leave the curve as it is,
+//so segments stay unchanged and their meshes are not regenerated while nothing is edited
+fn update_curves(
     mut road_segments: Query<&mut RoadSegment>,
-    transforms: Query<&Transform>,    
+    transforms: Query<&Transform>,
+    control_pts: Query<&ControlPointDraggable>,
+) {
+    for mut rs in road_segments.iter_mut() {
+        let input = rs.curve_input(&rs.control_points(&transforms, &control_pts));
+        if *rs.curve.input() != input {
+            rs.store_curve(input);
+        }
+    }
+}
+
+fn draw_curve_using_road_segment(
+    road_segments: Query<(Entity, &RoadSegment)>,
+    selection: Res<Selection>,
     mut gizmos: Gizmos,
-    ui_state: Res<UiState>,
 ) {
-    for rs in road_segments.iter_mut() {
-        
-         let positions: [Vec3; 4] = rs.pts_ids
-            .iter()
-            .map(|pt| transforms.get(*pt).unwrap().translation)
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap();
-
-        let curve = CubicBezier::new([positions]);
-        // let curve = CubicBSpline::new(positions);
-        // let curve = CubicCardinalSpline::new(0.7, positions); //this looks good
-        // let curve = CubicHermite::new(positions, [Vec3::splat(10.); 4]);
-
-        // let weights = [10.0, 10.0, 20.0, 10.0];
-        // let knots = [0.0, 0.0, 10.0, 20.0, 30.0, 40.0, 50.0, 50.0];
-        // let curve = CubicNurbs::new(positions, Some(weights), Some(knots))
-        //     .expect("NURBS construction failed!");
-
-        let subdivisions: usize = ui_state.sections_amnt.try_into().unwrap();
-
-        let positions = curve
-            .to_curve()
-            .iter_positions(subdivisions)
-            .collect::<Vec<Vec3>>();
-
-        // let positions = rs.curve_pts(&transforms, 100);
-        
-        gizmos.linestrip(
-            positions, 
-            Color::WHITE
-        );
+    for (id, rs) in road_segments.iter() {
+        gizmos.linestrip(rs.curve.iter_positions(100), curve_color(&selection, id));
     }
 }
 
 #[allow(dead_code)]
 fn sphere_along_curve_move_with_time(
     time: Res<Time>, 
+    slides: Query<&Slide>,
     road_segments: Query<&RoadSegment>,
     mut moving_spheres: Query<&mut Transform, With<MovingSphere>>,
 ){
     let t = (time.elapsed_seconds().sin() + 1.) / 2.;
 
-    for rs in road_segments.iter() {
-        let pos = rs.curve.to_curve().position(t);
+    for slide in slides.iter() {
+        let path = slide.path(&road_segments);
+        if path.segment_count() == 0 { continue; }
+        let pos = path.position(t * path.domain());
         for mut s in moving_spheres.iter_mut() {
             s.translation = pos;
         }
@@ -432,17 +946,76 @@ fn draw_shape(gizmos: &mut Gizmos<'_, '_>, op: OrientedPoint, local_space_pos: V
     gizmos.sphere(op.local_to_world_pos(local_space_pos), op.rot, 0.2, RED).resolution(8);
 }
 
+//carries up vector, distance, radius and profile along every slide so frames, textures and the tube
+//do not jump on joints. first segment starts with world up, next ones with the up, radius and profile
+//previous one ended with. bank is not carried, it is applied on top of these frames
+fn update_slide_frames(
+    slides: Query<&Slide>,
+    mut road_segments: Query<&mut RoadSegment>,
+    control_pts: Query<&ControlPointDraggable>,
+) {
+    for slide in slides.iter() {
+        let mut up = Vec3::Y;
+        let mut distance = 0.;
+        let mut joint: Option<Joint> = None;
+
+        for id in slide.segments.iter() {
+            let Ok(mut rs) = road_segments.get_mut(*id) else { continue; };
+            if let Some(joint) = &joint {
+                joint.apply_to(&mut rs);
+            }
+
+            if rs.start_distance != distance { rs.start_distance = distance; }
+            distance += rs.get_len();
+
+            let banks = [rs.start_pt_id(), rs.end_pt_id()]
+                .map(|pt_id| control_pts.get(pt_id).map_or(0., |cp| cp.bank));
+            if rs.banks != banks { rs.banks = banks; }
+
+            let start_up = rs.start_up_from(up);
+            if rs.start_up != start_up { rs.start_up = start_up; }
+
+            up = rs.end_up();
+            joint = Some(rs.joint(1));
+        }
+    }
+}
+
+//profile files are loaded later than segments and reloaded by the asset server when edited,
+//segments using them have to be rebuilt
+fn reload_profiles(
+    mut events: EventReader<AssetEvent<ProfileShape>>,
+    mut road_segments: Query<&mut RoadSegment>,
+) {
+    for event in events.read() {
+        let (AssetEvent::Added { id } | AssetEvent::Modified { id }) = event else { continue; };
+
+        for mut rs in road_segments.iter_mut() {
+            let uses = |profile: &ProfileKind| matches!(profile, ProfileKind::Asset(handle) if handle.id() == *id);
+            if uses(&rs.profile) || rs.end_profile.as_ref().is_some_and(uses) {
+                rs.set_changed();
+            }
+        }
+    }
+}
+
 fn draw_profile(
     ui_state: Res<UiState>,
+    profiles: Res<Assets<ProfileShape>>,
+    slides: Query<&Slide>,
     road_segments: Query<&RoadSegment>,
     mut moving_spheres: Query<&mut Transform, With<MovingSphere>>,
     mut gizmos: Gizmos
 ){
-    for rs in road_segments.iter() {
+    for slide in slides.iter() {
+        //t slider goes along the whole slide by distance, so it moves at constant speed
+        let distance = ui_state.t_value * slide.length(&road_segments);
+        let Some((rs, t)) = slide.locate_distance(distance, &road_segments) else { continue; };
+
         for mut sphere in moving_spheres.iter_mut() {
             
-            let t = ui_state.t_value;
-            let shape2d = ProfileShape::circle_8();
+            let Some(ring_profiles) = rs.ring_profiles(&profiles) else { continue; };
+            let shape2d = rs.ring_profile(&ring_profiles, t);
             
             let (center, profile_edges) 
                 = rs.get_profile_center_and_lines(t, &shape2d);
@@ -468,20 +1041,21 @@ fn draw_profile(
     }
 }
 
+//only segments whose curve, frames, profile or slide changed are rebuilt.
+//meshes are built on the async compute pool, segments keep showing old meshes until apply_mesh_tasks swaps them.
+//every level of detail is built at once so switching levels does not wait for a build
 fn generate_mesh(
-    mut road_segments: Query<&mut RoadSegment>,
-    asset_server: Res<AssetServer>,
-    mut mesh_asset_server: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut control_pts: Query<&mut Transform, With<ControlPointDraggable>>,
-    mut query: Query<(&mut CustomMesh, &mut Handle<Mesh>, &mut Handle<StandardMaterial>)>,
+    mut commands: Commands,
+    road_segments: Query<(Entity, Ref<RoadSegment>), With<CustomMesh>>,
+    slides: Query<Ref<Slide>>,
     ui_state: Res<UiState>,
+    //ui settings meshes were last built with, t value and others do not need rebuilding
+    mut built_settings: Local<Option<(i32, RingSpacing, f32, f32, f32)>>,
+    profiles: Res<Assets<ProfileShape>>,
     
     mut config_store: ResMut<GizmoConfigStore>,
     keyboard: Res<ButtonInput<KeyCode>>,
 ) {
-    // if control_pts.iter_mut().all(|p| !p.is_changed()) {return;};
-    
     //toggle gizmos drawing over objects
     if keyboard.just_pressed(KeyCode::KeyD) {
         for (_, config, _) in config_store.iter_mut() {
@@ -489,109 +1063,147 @@ fn generate_mesh(
         }
     }
 
-    for mut rs in road_segments.iter_mut() {
-        for (mut _custom_mesh, mut mesh_handle, mut material_handle) in query.iter_mut(){
-            
-            let shape2d = ProfileShape::circle_8();
-            
-            let mut control_pts_positions: Vec<Vec3> = rs.pts_ids
-                .iter()
-                .map(|pt_id| control_pts.get(*pt_id).unwrap().translation)
-                .collect();
-            
-            let control_pts_trms: Vec<&Transform> = control_pts
-                .iter()
-                .map(|x| x)
-                .collect();
-            
-            //locks mid points to start and end
-            //need to change its transform z scale to see the effect
-            control_pts_positions[1] = control_pts_trms[0].transform_point(-Vec3::Z * control_pts_trms[0].scale.z);
-            control_pts_positions[2] = control_pts_trms[3].transform_point( Vec3::Z * control_pts_trms[3].scale.z);
-            
-            let sections_amnt = ui_state.sections_amnt.try_into().unwrap();
+    let settings = (
+        ui_state.sections_amnt,
+        ui_state.ring_spacing,
+        ui_state.curve_tolerance,
+        ui_state.wall_thickness,
+        ui_state.uv_tile_size,
+    );
+    let settings_changed = *built_settings != Some(settings);
+    *built_settings = Some(settings);
 
-            rs.calc_and_store_curve_return_curve_pts(&control_pts_positions, sections_amnt);
-    
-            // Vertices
-            //needed for uniforming uvs. not used here
-            // let u_span = shape2d.calc_u_span();
-            let edge_ring_count= sections_amnt;
-
-            let mut verts = Vec::<Vec3>::new();
-            //normals not used but let them be to show how are calculated
-            let mut normals = Vec::<Vec3>::new();   
-            let mut uvs = Vec::<Vec2>::new();
-    
-            for ring in 0..=edge_ring_count {
-                
-                let t: f32 = ring as f32 / (edge_ring_count - 1) as f32;
-                let op = rs.get_bezier_oriented_point(t);
-    
-                for i in 0..shape2d.vertex_count() {
-                    verts.push(op.local_to_world_pos(shape2d.vertices[i].point));
-                    normals.push(op.local_to_world_vec(shape2d.vertices[i].normal));
-                    //coefficient to uniform uvs. doesnt work, not used.
-                    // let coeff = rs.get_approx_len()/u_span;
-                    let coeff = 1.;
-                    uvs.push(Vec2::new(shape2d.vertices[i].u , t * coeff));
-                }
+    let task_pool = AsyncComputeTaskPool::get();
+
+    for (id, rs) in road_segments.iter() {
+        //caps depend on segment being first or last in its slide
+        let slide = slides.get(rs.slide).ok();
+        let slide_changed = slide.as_ref().is_some_and(|s| s.is_changed());
+        if !settings_changed && !slide_changed && !rs.is_changed() { continue; }
+
+        //profile asset is not loaded yet, keep the old mesh. segment is rebuilt when it loads
+        let Some(ring_profiles) = rs.ring_profiles(&profiles) else { continue; };
+
+        let input = SegmentMeshInput {
+            segment: rs.clone(),
+            ring_profiles,
+            sections_amnt: ui_state.sections_amnt.try_into().unwrap(),
+            ring_spacing: ui_state.ring_spacing,
+            curve_tolerance: ui_state.curve_tolerance,
+            wall_thickness: ui_state.wall_thickness,
+            uv_tile_size: ui_state.uv_tile_size,
+            caps: [
+                slide.as_ref().and_then(|s| s.segments.first().copied()) == Some(id),
+                slide.as_ref().and_then(|s| s.last_segment()) == Some(id),
+            ],
+        };
+
+        //replaces a task still running for this segment, dropped task is cancelled
+        commands
+            .entity(id)
+            .insert(MeshTask(task_pool.spawn(async move { input.build() })));
+    }
+}
+
+//swaps finished meshes into the mesh assets segments already have
+fn apply_mesh_tasks(
+    mut commands: Commands,
+    mut road_segments: Query<(Entity, &RoadSegment, &SegmentLods, &mut MeshTask)>,
+    mut inner_walls: Query<&mut Visibility, With<InnerWall>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for (id, rs, lods, mut task) in road_segments.iter_mut() {
+        let Some(built) = block_on(future::poll_once(&mut task.0)) else { continue; };
+        commands.entity(id).remove::<MeshTask>();
+
+        let mut has_inner = false;
+        for ((lod, outer_handle), inner_handle) in built.into_iter().zip(lods.outer.iter()).zip(lods.inner.iter()) {
+            if let Some(mesh) = meshes.get_mut(outer_handle) {
+                *mesh = lod.outer;
             }
-            
-            //
-            //  A                   B
-            //  .___________________.
-            //  |    ring next      |
-            //  |                /  |
-            //  |             /     |
-            //  |          /        |
-            //  |       /           |
-            //  |    /              |
-            //  | /                 |
-            //  .___________________.
-            //  A    ring curr      B
-            //
-            // should be counter-clockwise in bevy
-            //
-    
-            // Triangles
-            let mut tri_indices = Vec::<u32>::new();
-    
-            for ring in 0..(edge_ring_count-1) {
-                
-                let root_idx = ring * shape2d.vertex_count();
-                let root_idx_next = (ring + 1) * shape2d.vertex_count();
-    
-                for line in (0..shape2d.line_count()).step_by(2) {
-                    
-                    let line_idx_a = shape2d.line_indices[line];
-                    let line_idx_b = shape2d.line_indices[line + 1];
-                    
-                    let curr_a = root_idx + line_idx_a;
-                    let curr_b = root_idx + line_idx_b;
-                    let next_a = root_idx_next + line_idx_a;
-                    let next_b = root_idx_next + line_idx_b;
-    
-                    tri_indices.push(curr_a as u32);
-                    tri_indices.push(curr_b as u32);
-                    tri_indices.push(next_b as u32);
-    
-                    tri_indices.push(curr_a as u32);
-                    tri_indices.push(next_b as u32);
-                    tri_indices.push(next_a as u32);
-                }
+            if let (Some(inner), Some(mesh)) = (lod.inner, meshes.get_mut(inner_handle)) {
+                *mesh = inner;
+                has_inner = true;
             }
-            
-            let new_mesh = Mesh::new(
-                PrimitiveTopology::TriangleList, 
-                RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-            )
-            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, verts)
-            .with_inserted_indices(Indices::U32(tri_indices))
-            // .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
-            .with_computed_normals();
-    
-            *mesh_handle = mesh_asset_server.add(new_mesh);
         }
+
+        if let Ok(mut visibility) = inner_walls.get_mut(rs.inner_wall) {
+            visibility.set_if_neq(if has_inner { Visibility::Inherited } else { Visibility::Hidden });
+        }
+    }
+}
+
+//picks level of detail of every segment by its distance to the camera
+fn select_lods(
+    ui_state: Res<UiState>,
+    cameras: Query<&GlobalTransform, With<PanOrbitCamera>>,
+    mut road_segments: Query<(&RoadSegment, &SegmentLods, &mut Handle<Mesh>)>,
+    mut inner_walls: Query<&mut Handle<Mesh>, (With<InnerWall>, Without<RoadSegment>)>,
+) {
+    let Ok(camera) = cameras.get_single() else { return; };
+    let eye = camera.translation();
+
+    for (rs, lods, mut mesh_handle) in road_segments.iter_mut() {
+        let distance = [0., 0.5, 1.]
+            .map(|t| rs.curve.position(t).distance(eye))
+            .into_iter()
+            .fold(f32::INFINITY, f32::min);
+        //every next level starts twice as far as the previous one
+        let level = if distance < ui_state.lod_distance {
+            0
+        } else {
+            ((distance / ui_state.lod_distance).log2() as usize + 1).min(LOD_COUNT - 1)
+        };
+
+        if *mesh_handle == lods.outer[level] { continue; }
+        *mesh_handle = lods.outer[level].clone();
+        if let Ok(mut inner_handle) = inner_walls.get_mut(rs.inner_wall) {
+            *inner_handle = lods.inner[level].clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use super::*;
+
+    fn segment(slide: Entity, from: Vec3, to: Vec3) -> RoadSegment {
+        let mut rs = RoadSegment { slide, ..default() };
+        rs.store_curve(CurveInput { pts: [from, from.lerp(to, 1. / 3.), from.lerp(to, 2. / 3.), to], ..default() });
+        rs
+    }
+
+    #[test]
+    fn next_segment_starts_where_the_previous_one_ends() {
+        let mut world = World::new();
+        let slide = world.spawn_empty().id();
+        let flume = ProfileKind::Flume { width: 2., height: 1. };
+        let first = RoadSegment {
+            end_profile: Some(flume.clone()),
+            blend: [0., 0.5],
+            radius: [1., 2.],
+            ..segment(slide, Vec3::ZERO, Vec3::X * 10.)
+        };
+        let second = RoadSegment {
+            profile: ProfileKind::HalfPipe { sides: 8, opening: 120. },
+            radius: [1., 3.],
+            ..segment(slide, Vec3::X * 10., Vec3::X * 20.)
+        };
+        let third = segment(slide, Vec3::X * 20., Vec3::X * 30.);
+        let ids = [first, second, third].map(|rs| world.spawn(rs).id());
+        world.entity_mut(slide).insert(Slide { segments: ids.to_vec() });
+
+        world.run_system_once(update_slide_frames);
+
+        let [first, second, third] = ids.map(|id| world.get::<RoadSegment>(id).unwrap().clone());
+        //joint inside the blend: second one goes on blending into the flume, keeps its own end radius
+        assert!(second.joint(0) == first.joint(1));
+        assert_eq!(second.end_profile, Some(flume.clone()));
+        assert_eq!(second.radius, [2., 3.]);
+        //blend ended on the flume, third one is a flume all the way
+        assert!(third.joint(0) == second.joint(1));
+        assert_eq!(third.profile, flume);
+        assert_eq!(third.radius[0], 3.);
     }
 }
@@ -0,0 +1,115 @@
+use bevy::{math::Vec3, reflect::Reflect};
+
+//lookup table between curve t and distance along the curve.
+//curve t is not uniform: points bunch up near control points, this lets us walk the curve by distance
+#[derive(Clone, Default, Reflect)]
+pub struct ArcLengthTable {
+    //ascending from 0 to 1
+    ts: Vec<f32>,
+    //distance from the curve start at each t, ascending from 0 to curve length
+    distances: Vec<f32>,
+}
+
+impl ArcLengthTable {
+    pub const DEFAULT_SAMPLES: usize = 128;
+
+    pub fn new(position: impl Fn(f32) -> Vec3, samples: usize) -> Self {
+        let samples = samples.max(2);
+        let mut ts = Vec::with_capacity(samples);
+        let mut distances = Vec::with_capacity(samples);
+
+        let mut prev = position(0.);
+        let mut dist = 0.;
+        for i in 0..samples {
+            let t = i as f32 / (samples - 1) as f32;
+            let pos = position(t);
+            dist += pos.distance(prev);
+            prev = pos;
+
+            ts.push(t);
+            distances.push(dist);
+        }
+
+        Self { ts, distances }
+    }
+
+    pub fn length(&self) -> f32 {
+        self.distances.last().copied().unwrap_or(0.)
+    }
+
+    pub fn t_at_distance(&self, distance: f32) -> f32 {
+        Self::remap(&self.distances, &self.ts, distance)
+    }
+
+    pub fn distance_at_t(&self, t: f32) -> f32 {
+        Self::remap(&self.ts, &self.distances, t)
+    }
+
+    //0 is the start of the curve, 1 is the end
+    pub fn t_at_fraction(&self, fraction: f32) -> f32 {
+        self.t_at_distance(fraction * self.length())
+    }
+
+    //finds value in ascending `from` and lerps the matching one from `to`
+    fn remap(from: &[f32], to: &[f32], value: f32) -> f32 {
+        let (Some(&first), Some(&last)) = (from.first(), from.last()) else { return 0.; };
+        if value <= first { return to[0]; }
+        if value >= last { return to[to.len() - 1]; }
+
+        let idx = from.partition_point(|&x| x < value).max(1);
+        let (a, b) = (from[idx - 1], from[idx]);
+        let k = if b - a > f32::EPSILON { (value - a) / (b - a) } else { 0. };
+
+        to[idx - 1] + (to[idx] - to[idx - 1]) * k
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f32 = 1e-4;
+
+    fn line() -> (ArcLengthTable, f32) {
+        let (a, b) = (Vec3::new(1., 2., 3.), Vec3::new(4., -2., 3.));
+        (ArcLengthTable::new(|t| a.lerp(b, t), ArcLengthTable::DEFAULT_SAMPLES), a.distance(b))
+    }
+
+    #[test]
+    fn straight_line_distance_is_t_times_length() {
+        let (table, len) = line();
+        assert!((table.length() - len).abs() < EPS);
+        for i in 0..=20 {
+            let t = i as f32 / 20.;
+            assert!((table.distance_at_t(t) - t * len).abs() < EPS);
+            assert!((table.t_at_distance(t * len) - t).abs() < EPS);
+            assert!((table.t_at_fraction(t) - t).abs() < EPS);
+        }
+    }
+
+    #[test]
+    fn ends_are_exact_and_outside_is_clamped() {
+        let (table, len) = line();
+        assert_eq!(table.t_at_distance(0.), 0.);
+        assert_eq!(table.t_at_distance(table.length()), 1.);
+        assert_eq!(table.t_at_distance(-1.), 0.);
+        assert_eq!(table.t_at_distance(len + 5.), 1.);
+        assert_eq!(table.distance_at_t(-0.5), 0.);
+        assert_eq!(table.distance_at_t(1.5), table.length());
+    }
+
+    #[test]
+    fn uneven_curve_t_is_walked_by_distance() {
+        //t squared along x: a quarter of the length is at t 0.5
+        let table = ArcLengthTable::new(|t| Vec3::X * t * t, ArcLengthTable::DEFAULT_SAMPLES);
+        assert!((table.length() - 1.).abs() < EPS);
+        assert!((table.t_at_fraction(0.25) - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn empty_table_is_zero() {
+        let table = ArcLengthTable::default();
+        assert_eq!(table.length(), 0.);
+        assert_eq!(table.t_at_distance(1.), 0.);
+    }
+}
@@ -0,0 +1,234 @@
+use bevy::{
+    math::{
+        cubic_splines::{
+            CubicBSpline, CubicBezier, CubicCardinalSpline, CubicGenerator, CubicHermite, CubicNurbs,
+            RationalCurve, RationalGenerator, RationalSegment,
+        },
+        Vec3,
+    },
+    reflect::{FromReflect, Reflect, ReflectRef},
+};
+use serde::{Deserialize, Serialize};
+
+//which spline a road segment is built with. every kind is built from the same 4 control points.
+//only bezier and hermite leave an end point along its forward, so only they are smooth across a joint.
+//the others leave it towards the next mid point and kink on a joint unless the points line up
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Reflect)]
+pub enum CurveKind {
+    //mid points are tangent handles locked to the end points
+    #[default]
+    Bezier,
+    //goes through all 4 points
+    CatmullRom,
+    //smooth, goes through the end points only
+    BSpline,
+    //goes through all 4 points, end tangents are taken from the end points like bezier handles,
+    //so it is smooth across joints too
+    Hermite,
+    //bezier like, but mid points pull the curve by their weights
+    Nurbs,
+}
+
+impl CurveKind {
+    pub const ALL: [CurveKind; 5] = [
+        CurveKind::Bezier,
+        CurveKind::CatmullRom,
+        CurveKind::BSpline,
+        CurveKind::Hermite,
+        CurveKind::Nurbs,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CurveKind::Bezier => "Bezier",
+            CurveKind::CatmullRom => "Catmull-Rom",
+            CurveKind::BSpline => "B-spline",
+            CurveKind::Hermite => "Hermite",
+            CurveKind::Nurbs => "NURBS",
+        }
+    }
+
+    //mid points are free points the curve is shaped by, not handles
+    pub fn has_free_mid_points(self) -> bool {
+        self != CurveKind::Bezier
+    }
+}
+
+//everything a segment curve is built from. kept with the curve, so it is rebuilt only when this changes.
+//the inspector shows it in place of the spans
+#[derive(Clone, Copy, PartialEq, Reflect)]
+pub struct CurveInput {
+    pub kind: CurveKind,
+    pub pts: [Vec3; 4],
+    //velocities at the start and the end, only hermite uses them
+    pub end_tangents: [Vec3; 2],
+    //per control point, only nurbs uses them
+    pub weights: [f32; 4],
+}
+
+impl Default for CurveInput {
+    fn default() -> Self {
+        //no curve: infinitely far from everything
+        Self {
+            kind: CurveKind::Bezier,
+            pts: [Vec3::INFINITY; 4],
+            end_tangents: [Vec3::ZERO; 2],
+            weights: [1.; 4],
+        }
+    }
+}
+
+impl CurveInput {
+    pub fn build(self) -> SegmentCurve {
+        let CurveInput { kind, pts, end_tangents, weights } = self;
+        let bezier = || RationalCurve::from(CubicBezier::new([pts]).to_curve());
+
+        let curve = match kind {
+            CurveKind::Bezier => bezier(),
+            CurveKind::CatmullRom => CubicCardinalSpline::new_catmull_rom(pts).to_curve().into(),
+            //repeated end points pin the curve to them
+            CurveKind::BSpline => CubicBSpline::new([pts[0], pts[0], pts[0], pts[1], pts[2], pts[3], pts[3], pts[3]])
+                .to_curve()
+                .into(),
+            //3 spans, each a third of the segment t, so velocities by span t are a third of the ones by segment t
+            CurveKind::Hermite => CubicHermite::new(
+                    pts,
+                    [end_tangents[0] / 3., (pts[2] - pts[0]) / 2., (pts[3] - pts[1]) / 2., end_tangents[1] / 3.],
+                )
+                .to_curve()
+                .into(),
+            CurveKind::Nurbs => CubicNurbs::new(pts, Some(weights), None::<Vec<f32>>)
+                .map(|nurbs| nurbs.to_curve())
+                .unwrap_or_else(|_| bezier()),
+        };
+
+        SegmentCurve::new(curve, self)
+    }
+}
+
+//curve of one segment with t from 0 to 1 whatever the curve kind and its amount of inner spans is
+#[derive(Clone, Reflect)]
+#[reflect(from_reflect = false)]
+pub struct SegmentCurve {
+    //spans do not reflect, input shows what they are built from
+    #[reflect(ignore)]
+    curve: RationalCurve<Vec3>,
+    domain: f32,
+    input: CurveInput,
+}
+
+//spans are built again from the reflected input, so a reflected copy is the same curve
+impl FromReflect for SegmentCurve {
+    fn from_reflect(reflect: &dyn Reflect) -> Option<Self> {
+        let ReflectRef::Struct(s) = reflect.reflect_ref() else { return None; };
+        CurveInput::from_reflect(s.field("input")?).map(CurveInput::build)
+    }
+}
+
+impl Default for SegmentCurve {
+    fn default() -> Self {
+        CurveInput::default().build()
+    }
+}
+
+impl SegmentCurve {
+    fn new(curve: RationalCurve<Vec3>, input: CurveInput) -> Self {
+        let domain = curve.domain();
+        Self { curve, domain, input }
+    }
+
+    pub fn input(&self) -> &CurveInput {
+        &self.input
+    }
+
+    pub fn position(&self, t: f32) -> Vec3 {
+        let (span, local_t) = self.span(t);
+        span.position(local_t)
+    }
+
+    //derivative by segment t
+    pub fn velocity(&self, t: f32) -> Vec3 {
+        let (span, local_t) = self.span(t);
+        span.velocity(local_t) / span.knot_span * self.domain
+    }
+
+    pub fn iter_positions(&self, subdivisions: usize) -> impl Iterator<Item = Vec3> + '_ {
+        (0..=subdivisions).map(move |i| self.position(i as f32 / subdivisions as f32))
+    }
+
+    //segment t -> inner span and t inside that span
+    fn span(&self, t: f32) -> (&RationalSegment<Vec3>, f32) {
+        let mut t = t.clamp(0., 1.) * self.domain;
+        let spans = self.curve.segments();
+        for span in spans.iter() {
+            if t < span.knot_span {
+                return (span, t / span.knot_span);
+            }
+            t -= span.knot_span;
+        }
+
+        (&spans[spans.len() - 1], 1.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f32 = 1e-4;
+
+    fn input(kind: CurveKind) -> CurveInput {
+        CurveInput {
+            kind,
+            pts: [Vec3::ZERO, Vec3::new(2., 1., 0.), Vec3::new(5., 1., 3.), Vec3::new(6., 0., 6.)],
+            end_tangents: [Vec3::X * 4., Vec3::Z * 5.],
+            weights: [1., 2., 3., 1.],
+        }
+    }
+
+    #[test]
+    fn every_kind_starts_and_ends_on_the_end_points() {
+        for kind in CurveKind::ALL {
+            let input = input(kind);
+            let curve = input.build();
+            assert!(curve.position(0.).distance(input.pts[0]) < EPS, "{}", kind.name());
+            assert!(curve.position(1.).distance(input.pts[3]) < EPS, "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn catmull_rom_goes_through_every_point() {
+        let input = input(CurveKind::CatmullRom);
+        let curve = input.build();
+        //one span between each two points
+        for (i, pt) in input.pts.iter().enumerate() {
+            assert!(curve.position(i as f32 / 3.).distance(*pt) < EPS);
+        }
+    }
+
+    #[test]
+    fn hermite_leaves_and_enters_with_the_end_tangents() {
+        let input = input(CurveKind::Hermite);
+        let curve = input.build();
+        assert!(curve.velocity(0.).distance(input.end_tangents[0]) < EPS);
+        assert!(curve.velocity(1.).distance(input.end_tangents[1]) < EPS);
+    }
+
+    #[test]
+    fn bezier_tangents_are_the_handles() {
+        let input = input(CurveKind::Bezier);
+        let curve = input.build();
+        assert!(curve.velocity(0.).distance((input.pts[1] - input.pts[0]) * 3.) < EPS);
+        assert!(curve.velocity(1.).distance((input.pts[3] - input.pts[2]) * 3.) < EPS);
+    }
+
+    #[test]
+    fn reflected_copy_is_the_same_curve() {
+        let curve = input(CurveKind::Nurbs).build();
+        let copy = SegmentCurve::from_reflect(&curve).unwrap();
+        for i in 0..=10 {
+            let t = i as f32 / 10.;
+            assert!(copy.position(t).distance(curve.position(t)) < EPS);
+        }
+    }
+}
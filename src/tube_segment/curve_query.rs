@@ -0,0 +1,197 @@
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use bevy_mod_raycast::prelude::*;
+use super::{oriented_point::OrientedPoint, RoadSegment};
+
+//samples along a segment the nearest one is searched among before it is refined
+const SAMPLES: usize = 64;
+//refining stops when the step in t gets this small
+const MIN_STEP: f32 = 1e-5;
+
+//point of a slide path nearest to a world point or a ray
+#[derive(Clone, Copy)]
+pub struct CurveHit {
+    pub segment: Entity,
+    pub t: f32,
+    //from the point, or from the ray for ray queries
+    pub distance: f32,
+    //banked frame of the path at t, same as mesh rings have
+    pub frame: OrientedPoint,
+}
+
+impl RoadSegment {
+    //t where the curve is nearest by a distance and that distance.
+    //coarse samples first, then halving steps around the best one
+    fn closest_t(&self, distance: impl Fn(Vec3) -> f32) -> (f32, f32) {
+        let dist = |t: f32| distance(self.curve.position(t));
+
+        let mut best = (0..=SAMPLES)
+            .map(|i| i as f32 / SAMPLES as f32)
+            .min_by(|a, b| dist(*a).total_cmp(&dist(*b)))
+            .unwrap_or(0.);
+        let mut step = 1. / SAMPLES as f32;
+        while step > MIN_STEP {
+            step *= 0.5;
+            for t in [(best - step).max(0.), (best + step).min(1.)] {
+                if dist(t) < dist(best) { best = t; }
+            }
+        }
+
+        (best, dist(best))
+    }
+
+    pub fn closest_to_point(&self, pos: Vec3) -> (f32, f32) {
+        self.closest_t(|p| p.distance(pos))
+    }
+
+    pub fn closest_to_ray(&self, ray: Ray3d) -> (f32, f32) {
+        self.closest_t(|p| ray_distance(ray, p))
+    }
+
+    fn hit(&self, segment: Entity, (t, distance): (f32, f32)) -> CurveHit {
+        CurveHit {
+            segment,
+            t,
+            distance,
+            frame: self.get_banked_oriented_points(&[t])[0],
+        }
+    }
+}
+
+//distance from a point to the part of the ray in front of its origin
+fn ray_distance(ray: Ray3d, p: Vec3) -> f32 {
+    let offset = p - ray.origin;
+    let along = offset.dot(*ray.direction).max(0.);
+    (offset - *ray.direction * along).length()
+}
+
+//nearest point over the given segments, all of them or the one under the cursor
+pub fn closest_to_point<'a>(pos: Vec3, road_segments: impl IntoIterator<Item = (Entity, &'a RoadSegment)>) -> Option<CurveHit> {
+    nearest(road_segments, |rs| rs.closest_to_point(pos))
+}
+
+pub fn closest_to_ray<'a>(ray: Ray3d, road_segments: impl IntoIterator<Item = (Entity, &'a RoadSegment)>) -> Option<CurveHit> {
+    nearest(road_segments, |rs| rs.closest_to_ray(ray))
+}
+
+fn nearest<'a>(
+    road_segments: impl IntoIterator<Item = (Entity, &'a RoadSegment)>,
+    closest: impl Fn(&RoadSegment) -> (f32, f32),
+) -> Option<CurveHit> {
+    road_segments
+        .into_iter()
+        .map(|(id, rs)| (id, rs, closest(rs)))
+        //segments without a computed curve yet are infinitely far
+        .filter(|(_, _, (_, distance))| distance.is_finite())
+        .min_by(|a, b| a.2.1.total_cmp(&b.2.1))
+        .map(|(id, rs, closest)| rs.hit(id, closest))
+}
+
+//slide point under the mouse cursor, None when the cursor is not over a slide
+#[derive(Resource, Default)]
+pub struct SlideHover {
+    pub hit: Option<CurveHit>,
+}
+
+//tube the cursor ray hits picks the segment, the cursor is on its curve nearest to the hit.
+//segments without a mesh yet are hovered when the ray passes them closer than their radius
+pub fn update_slide_hover(
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    windows: Query<&Window>,
+    mut contexts: EguiContexts,
+    mut raycast: Raycast,
+    mut hover: ResMut<SlideHover>,
+    road_segments: Query<(Entity, &RoadSegment)>,
+) {
+    let (camera, camera_transform) = cameras.single();
+    let ray = windows
+        .single()
+        .cursor_position()
+        .and_then(|pos| camera.viewport_to_world(camera_transform, pos));
+    let ctx = contexts.ctx_mut();
+    let Some(ray) = ray.filter(|_| !ctx.is_pointer_over_area()) else {
+        hover.hit = None;
+        return;
+    };
+
+    let tube_hit = raycast
+        .cast_ray(ray, &RaycastSettings { filter: &|e| road_segments.contains(e), ..default() })
+        .first()
+        .map(|(e, hit)| (*e, hit.position()));
+
+    hover.hit = match tube_hit {
+        Some((segment, pos)) => closest_to_point(pos, road_segments.get(segment)),
+        None => closest_to_ray(ray, road_segments.iter()).filter(|hit| {
+            road_segments
+                .get(hit.segment)
+                .is_ok_and(|(_, rs)| hit.distance <= rs.radius[0].max(rs.radius[1]))
+        }),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tube_segment::curve_kind::CurveInput;
+    use super::*;
+
+    const EPS: f32 = 1e-3;
+
+    //points spread evenly, so t is the fraction of the length
+    fn straight(from: Vec3, to: Vec3) -> RoadSegment {
+        let mut rs = RoadSegment::default();
+        rs.store_curve(CurveInput { pts: [from, from.lerp(to, 1. / 3.), from.lerp(to, 2. / 3.), to], ..default() });
+        rs
+    }
+
+    #[test]
+    fn point_on_segment_gives_its_t() {
+        let (t, distance) = straight(Vec3::ZERO, Vec3::X * 10.).closest_to_point(Vec3::X * 3.);
+        assert!((t - 0.3).abs() < EPS);
+        assert!(distance < EPS);
+    }
+
+    #[test]
+    fn point_beyond_an_end_clamps_to_it() {
+        let rs = straight(Vec3::ZERO, Vec3::X * 10.);
+        let (t, distance) = rs.closest_to_point(Vec3::new(-3., 4., 0.));
+        assert!(t < EPS);
+        assert!((distance - 5.).abs() < EPS);
+
+        let (t, distance) = rs.closest_to_point(Vec3::X * 12.);
+        assert!(t > 1. - EPS);
+        assert!((distance - 2.).abs() < EPS);
+    }
+
+    #[test]
+    fn ray_crossing_the_curve_hits_it() {
+        let ray = Ray3d::new(Vec3::new(4., 5., 0.), Vec3::NEG_Y);
+        let (t, distance) = straight(Vec3::ZERO, Vec3::X * 10.).closest_to_ray(ray);
+        assert!((t - 0.4).abs() < EPS);
+        assert!(distance < EPS);
+    }
+
+    #[test]
+    fn ray_pointing_away_is_as_far_as_its_origin() {
+        let ray = Ray3d::new(Vec3::new(4., 5., 0.), Vec3::Y);
+        assert!((ray_distance(ray, Vec3::X * 4.) - 5.).abs() < EPS);
+
+        let (t, distance) = straight(Vec3::ZERO, Vec3::X * 10.).closest_to_ray(ray);
+        assert!((t - 0.4).abs() < EPS);
+        assert!((distance - 5.).abs() < EPS);
+    }
+
+    #[test]
+    fn nearest_picks_the_closer_segment() {
+        let near = straight(Vec3::Z * 3., Vec3::new(10., 0., 3.));
+        let far = straight(Vec3::ZERO, Vec3::X * 10.);
+        let (near_id, far_id) = (Entity::from_raw(1), Entity::from_raw(2));
+
+        let hit = closest_to_point(Vec3::new(5., 0., 2.), [(far_id, &far), (near_id, &near)]).unwrap();
+        assert_eq!(hit.segment, near_id);
+        assert!((hit.t - 0.5).abs() < EPS);
+        assert!((hit.distance - 1.).abs() < EPS);
+        assert!(hit.frame.pos.distance(Vec3::new(5., 0., 3.)) < EPS);
+
+        assert!(closest_to_point(Vec3::ZERO, std::iter::empty()).is_none());
+    }
+}
@@ -0,0 +1,107 @@
+use bevy::{color::palettes::css::{BLUE, LIME, RED}, prelude::*};
+use bevy_egui::EguiContexts;
+use crate::my_ui::{DragMode, UiState};
+
+//what a dragged point is kept on. plane and axis go through the point where the drag started
+#[derive(Clone, Copy)]
+pub enum DragConstraint {
+    Plane(Dir3),
+    Axis(Dir3),
+}
+
+impl DragConstraint {
+    pub fn new(mode: DragMode, camera: &GlobalTransform) -> Self {
+        let forward = camera.forward();
+        match mode {
+            DragMode::View => DragConstraint::Plane(forward),
+            DragMode::Ground => DragConstraint::Plane(Dir3::Y),
+            //looking straight down there is no upright plane facing the camera, any one does
+            DragMode::Vertical => DragConstraint::Plane(
+                Dir3::new(forward.with_y(0.)).unwrap_or(Dir3::Z)
+            ),
+            DragMode::AxisX => DragConstraint::Axis(Dir3::X),
+            DragMode::AxisY => DragConstraint::Axis(Dir3::Y),
+            DragMode::AxisZ => DragConstraint::Axis(Dir3::Z),
+        }
+    }
+
+    //point of the plane or the axis under the cursor ray.
+    //None if the ray misses the plane or runs along the axis
+    pub fn hit(&self, origin: Vec3, ray: Ray3d) -> Option<Vec3> {
+        match *self {
+            DragConstraint::Plane(normal) => ray
+                .intersect_plane(origin, InfinitePlane3d::new(normal))
+                .map(|d| ray.get_point(d)),
+            DragConstraint::Axis(axis) => {
+                //closest point of the axis line to the ray
+                let w = origin - ray.origin;
+                let b = axis.dot(*ray.direction);
+                let denom = 1. - b * b;
+                if denom < 1e-4 { return None; }
+                let s = (b * ray.direction.dot(w) - axis.dot(w)) / denom;
+                Some(origin + axis * s)
+            }
+        }
+    }
+}
+
+//drag of one control point. point keeps its offset from the cursor, so it does not jump to it when grabbed
+pub struct ActiveDrag {
+    pub point: Entity,
+    pub mode: DragMode,
+    pub origin: Vec3,
+    pub constraint: DragConstraint,
+    offset: Vec3,
+}
+
+impl ActiveDrag {
+    pub fn start(point: Entity, origin: Vec3, mode: DragMode, ray: Ray3d, camera: &GlobalTransform) -> Option<Self> {
+        let constraint = DragConstraint::new(mode, camera);
+        let hit = constraint.hit(origin, ray)?;
+        Some(Self { point, mode, origin, constraint, offset: origin - hit })
+    }
+
+    pub fn target(&self, ray: Ray3d) -> Option<Vec3> {
+        self.constraint.hit(self.origin, ray).map(|hit| hit + self.offset)
+    }
+
+    //line of the axis, plane is shown by the control points plane
+    pub fn draw_guide(&self, gizmos: &mut Gizmos) {
+        if let DragConstraint::Axis(axis) = self.constraint {
+            let color = if axis == Dir3::X { RED } else if axis == Dir3::Y { LIME } else { BLUE };
+            gizmos.line(self.origin - axis * 1000., self.origin + axis * 1000., color);
+        }
+    }
+}
+
+//blender like: g frees the point in the view plane, x/y/z lock it to an axis, the same key again unlocks.
+//h keeps the height, v moves in an upright plane
+pub fn drag_mode_hotkeys(
+    mut contexts: EguiContexts,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut ui_state: ResMut<UiState>,
+) {
+    if contexts.ctx_mut().wants_keyboard_input() { return; }
+    //ctrl+z is undo
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) { return; }
+
+    let current = ui_state.drag_mode;
+    let axis_key = |key: KeyCode, axis: DragMode| {
+        keys.just_pressed(key).then_some(if current == axis { DragMode::View } else { axis })
+    };
+    let mode = if keys.just_pressed(KeyCode::KeyG) {
+        Some(DragMode::View)
+    } else if keys.just_pressed(KeyCode::KeyH) {
+        Some(DragMode::Ground)
+    } else if keys.just_pressed(KeyCode::KeyV) {
+        Some(DragMode::Vertical)
+    } else {
+        axis_key(KeyCode::KeyX, DragMode::AxisX)
+            .or_else(|| axis_key(KeyCode::KeyY, DragMode::AxisY))
+            .or_else(|| axis_key(KeyCode::KeyZ, DragMode::AxisZ))
+    };
+
+    if let Some(mode) = mode {
+        ui_state.map_unchanged(|s| &mut s.drag_mode).set_if_neq(mode);
+    }
+}
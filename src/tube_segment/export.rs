@@ -0,0 +1,622 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use bevy::{
+    asset::LoadState,
+    ecs::{system::RunSystemOnce, world::Command},
+    prelude::*,
+    render::mesh::VertexAttributeValues,
+};
+use serde_json::{json, Value};
+use thiserror::Error;
+use crate::my_ui::UiState;
+use super::{
+    default_slide_positions,
+    park::{ParkError, ParkFile},
+    profile_asset::ProfileShapeLoader,
+    profile_shape::{ProfileKind, ProfileShape},
+    slide::{spawn_slide, Slide},
+    tube_mesh::{end_ring, rings_meet, SegmentMeshInput},
+    update_curves, update_slide_frames, RoadSegment, SegmentLods,
+};
+
+//textures are read from here when embedded or copied next to exported files
+pub const ASSETS_DIR: &str = "assets";
+pub const EXPORT_DIR: &str = "exports";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    //binary gltf 2.0, textures embedded
+    Gltf,
+    //with .mtl and textures next to it
+    Obj,
+    //binary, geometry only. needs wall thickness so the tube is closed
+    Stl,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [ExportFormat::Gltf, ExportFormat::Obj, ExportFormat::Stl];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportFormat::Gltf => "glTF",
+            ExportFormat::Obj => "OBJ",
+            ExportFormat::Stl => "STL",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Gltf => "glb",
+            ExportFormat::Obj => "obj",
+            ExportFormat::Stl => "stl",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        Self::ALL.into_iter().find(|f| f.extension() == extension)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("could not write export: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not write gltf json: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("unknown export format of {0}, expected .glb, .obj or .stl")]
+    UnknownFormat(PathBuf),
+    #[error("stl needs a closed tube, set wall thickness above 0")]
+    NotWatertight,
+    #[error("stl needs a closed tube, rings of these segments do not meet: {0}")]
+    OpenJoints(String),
+    #[error("there are no slide meshes to export")]
+    NothingToExport,
+    #[error(transparent)]
+    Park(#[from] ParkError),
+    #[error("slide {0} of the park could not be spawned")]
+    SlideNotSpawned(usize),
+    #[error("profile could not be loaded: {0}")]
+    Profile(String),
+}
+
+pub struct ExportMaterial {
+    pub name: String,
+    pub base_color: Color,
+    pub roughness: f32,
+    pub metallic: f32,
+    //path inside assets
+    pub texture: Option<PathBuf>,
+}
+
+impl ExportMaterial {
+    fn new(name: String, material: &StandardMaterial, texture: Option<PathBuf>) -> Self {
+        Self {
+            name,
+            base_color: material.base_color,
+            roughness: material.perceptual_roughness,
+            metallic: material.metallic,
+            texture,
+        }
+    }
+}
+
+pub struct ExportMesh {
+    pub name: String,
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+    //index in ExportScene::materials
+    pub material: usize,
+}
+
+impl ExportMesh {
+    //None for meshes without triangles, like the ones not built yet
+    fn from_mesh(name: String, mesh: &Mesh, material: usize) -> Option<Self> {
+        let positions = mesh.attribute(Mesh::ATTRIBUTE_POSITION)?.as_float3()?.to_vec();
+        let normals = mesh.attribute(Mesh::ATTRIBUTE_NORMAL)?.as_float3()?.to_vec();
+        let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0) else { return None; };
+        let indices: Vec<u32> = mesh.indices()?.iter().map(|i| i as u32).collect();
+        if indices.is_empty() { return None; }
+
+        Some(Self { name, positions, normals, uvs: uvs.clone(), indices, material })
+    }
+
+    fn triangles(&self) -> impl Iterator<Item = [Vec3; 3]> + '_ {
+        self.indices
+            .chunks_exact(3)
+            .map(|tri| [0, 1, 2].map(|k| Vec3::from(self.positions[tri[k] as usize])))
+    }
+}
+
+//meshes of all slides with their materials, in world space
+#[derive(Default)]
+pub struct ExportScene {
+    pub meshes: Vec<ExportMesh>,
+    pub materials: Vec<ExportMaterial>,
+    //tubes with wall thickness are closed by lips and caps, see the tests of tube_mesh
+    pub watertight: bool,
+    //joints where the end ring of a segment is not the start ring of the next one, named by both segments
+    pub open_joints: Vec<String>,
+}
+
+impl ExportScene {
+    //index of the material in materials, added on first use. materials shared by meshes are written once
+    fn material_index(
+        &mut self,
+        material_ids: &mut HashMap<AssetId<StandardMaterial>, usize>,
+        id: AssetId<StandardMaterial>,
+        material: &StandardMaterial,
+    ) -> usize {
+        *material_ids.entry(id).or_insert_with(|| {
+            let texture = material.base_color_texture
+                .as_ref()
+                .and_then(|t| t.path())
+                .map(|p| p.path().to_path_buf());
+            self.materials.push(ExportMaterial::new(format!("material_{}", self.materials.len()), material, texture));
+            self.materials.len() - 1
+        })
+    }
+
+    pub fn write(&self, path: &Path, format: ExportFormat) -> Result<(), ExportError> {
+        if self.meshes.is_empty() {
+            return Err(ExportError::NothingToExport);
+        }
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        match format {
+            ExportFormat::Gltf => self.write_glb(path),
+            ExportFormat::Obj => self.write_obj(path),
+            ExportFormat::Stl => self.write_stl(path),
+        }
+    }
+
+    fn write_glb(&self, path: &Path) -> Result<(), ExportError> {
+        const FLOAT: u32 = 5126;
+        const UNSIGNED_INT: u32 = 5125;
+        const ARRAY_BUFFER: u32 = 34962;
+        const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+        const REPEAT: u32 = 10497;
+
+        let mut bin = Vec::<u8>::new();
+        let mut views = Vec::<Value>::new();
+        let mut accessors = Vec::<Value>::new();
+
+        //textures are embedded once even if several materials use them
+        let mut images = Vec::<Value>::new();
+        let mut image_ids = HashMap::<PathBuf, usize>::new();
+        let mut materials = Vec::<Value>::new();
+        for material in self.materials.iter() {
+            let color = material.base_color.to_linear();
+            let mut pbr = json!({
+                "baseColorFactor": [color.red, color.green, color.blue, color.alpha],
+                "metallicFactor": material.metallic,
+                "roughnessFactor": material.roughness,
+            });
+
+            let image = material.texture.as_ref().and_then(|texture| {
+                if let Some(&id) = image_ids.get(texture) { return Some(id); }
+                let mime = match texture.extension()?.to_str()? {
+                    "png" => "image/png",
+                    "jpg" | "jpeg" => "image/jpeg",
+                    _ => return None,
+                };
+                let bytes = fs::read(Path::new(ASSETS_DIR).join(texture))
+                    .map_err(|e| warn!("export: texture {texture:?} is skipped: {e}"))
+                    .ok()?;
+                let view = push_buffer_view(&mut bin, &mut views, &bytes, None);
+                images.push(json!({ "bufferView": view, "mimeType": mime }));
+                image_ids.insert(texture.clone(), images.len() - 1);
+                Some(images.len() - 1)
+            });
+            //one texture per image, they all repeat
+            if let Some(image) = image {
+                pbr["baseColorTexture"] = json!({ "index": image });
+            }
+
+            materials.push(json!({ "name": material.name, "pbrMetallicRoughness": pbr }));
+        }
+        let textures: Vec<Value> = (0..images.len()).map(|i| json!({ "source": i, "sampler": 0 })).collect();
+
+        let mut meshes = Vec::<Value>::new();
+        for mesh in self.meshes.iter() {
+            let (min, max) = mesh.positions.iter().fold(
+                (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+                |(min, max), p| (min.min(Vec3::from(*p)), max.max(Vec3::from(*p))),
+            );
+
+            let position = push_accessor(
+                &mut bin, &mut views, &mut accessors,
+                &float_bytes(mesh.positions.iter().flatten()),
+                ARRAY_BUFFER,
+                json!({ "componentType": FLOAT, "count": mesh.positions.len(), "type": "VEC3", "min": min.to_array(), "max": max.to_array() }),
+            );
+            let normal = push_accessor(
+                &mut bin, &mut views, &mut accessors,
+                &float_bytes(mesh.normals.iter().flatten()),
+                ARRAY_BUFFER,
+                json!({ "componentType": FLOAT, "count": mesh.normals.len(), "type": "VEC3" }),
+            );
+            let uv = push_accessor(
+                &mut bin, &mut views, &mut accessors,
+                &float_bytes(mesh.uvs.iter().flatten()),
+                ARRAY_BUFFER,
+                json!({ "componentType": FLOAT, "count": mesh.uvs.len(), "type": "VEC2" }),
+            );
+            let indices: Vec<u8> = mesh.indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+            let indices = push_accessor(
+                &mut bin, &mut views, &mut accessors,
+                &indices,
+                ELEMENT_ARRAY_BUFFER,
+                json!({ "componentType": UNSIGNED_INT, "count": mesh.indices.len(), "type": "SCALAR" }),
+            );
+
+            meshes.push(json!({
+                "name": mesh.name,
+                "primitives": [{
+                    "attributes": { "POSITION": position, "NORMAL": normal, "TEXCOORD_0": uv },
+                    "indices": indices,
+                    "material": mesh.material,
+                }],
+            }));
+        }
+
+        let nodes: Vec<Value> = self.meshes
+            .iter()
+            .enumerate()
+            .map(|(i, mesh)| json!({ "name": mesh.name, "mesh": i }))
+            .collect();
+        let node_ids: Vec<usize> = (0..nodes.len()).collect();
+
+        let gltf = json!({
+            "asset": { "version": "2.0", "generator": env!("CARGO_PKG_NAME") },
+            "scene": 0,
+            "scenes": [{ "nodes": node_ids }],
+            "nodes": nodes,
+            "meshes": meshes,
+            "materials": materials,
+            "textures": textures,
+            "images": images,
+            "samplers": [{ "wrapS": REPEAT, "wrapT": REPEAT }],
+            "accessors": accessors,
+            "bufferViews": views,
+            "buffers": [{ "byteLength": bin.len() }],
+        });
+
+        //glb: header, json chunk padded with spaces, binary chunk padded with zeros
+        let mut json_bytes = serde_json::to_vec(&gltf)?;
+        pad_to_4(&mut json_bytes, b' ');
+        pad_to_4(&mut bin, 0);
+        let total_len = 12 + 8 + json_bytes.len() + 8 + bin.len();
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(b"glTF")?;
+        file.write_all(&2u32.to_le_bytes())?;
+        file.write_all(&(total_len as u32).to_le_bytes())?;
+        file.write_all(&(json_bytes.len() as u32).to_le_bytes())?;
+        file.write_all(b"JSON")?;
+        file.write_all(&json_bytes)?;
+        file.write_all(&(bin.len() as u32).to_le_bytes())?;
+        file.write_all(b"BIN\0")?;
+        file.write_all(&bin)?;
+        file.flush()?;
+
+        Ok(())
+    }
+
+    fn write_obj(&self, path: &Path) -> Result<(), ExportError> {
+        let mtl_path = path.with_extension("mtl");
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        let mut mtl = BufWriter::new(File::create(&mtl_path)?);
+        for material in self.materials.iter() {
+            let color = material.base_color.to_srgba();
+            writeln!(mtl, "newmtl {}", material.name)?;
+            writeln!(mtl, "Kd {} {} {}", color.red, color.green, color.blue)?;
+            writeln!(mtl, "d {}", color.alpha)?;
+            //texture is copied next to the obj so the export can be moved around
+            if let Some((texture, file_name)) = material.texture.as_ref().and_then(|t| Some((t, t.file_name()?))) {
+                match fs::copy(Path::new(ASSETS_DIR).join(texture), dir.join(file_name)) {
+                    Ok(_) => writeln!(mtl, "map_Kd {}", file_name.to_string_lossy())?,
+                    Err(e) => warn!("export: texture {texture:?} is skipped: {e}"),
+                }
+            }
+            writeln!(mtl)?;
+        }
+        mtl.flush()?;
+
+        let mut obj = BufWriter::new(File::create(path)?);
+        let mtl_name = mtl_path.file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+        writeln!(obj, "mtllib {mtl_name}")?;
+
+        //obj indices are global for the whole file and start from 1
+        let mut base = 1;
+        for mesh in self.meshes.iter() {
+            writeln!(obj, "o {}", mesh.name)?;
+            writeln!(obj, "usemtl {}", self.materials[mesh.material].name)?;
+            for [x, y, z] in mesh.positions.iter() {
+                writeln!(obj, "v {x} {y} {z}")?;
+            }
+            //obj v goes up, bevy v goes down
+            for [u, v] in mesh.uvs.iter() {
+                writeln!(obj, "vt {u} {}", 1. - v)?;
+            }
+            for [x, y, z] in mesh.normals.iter() {
+                writeln!(obj, "vn {x} {y} {z}")?;
+            }
+            for tri in mesh.indices.chunks_exact(3) {
+                let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| i as usize + base);
+                writeln!(obj, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+            }
+            base += mesh.positions.len();
+        }
+        obj.flush()?;
+
+        Ok(())
+    }
+
+    fn write_stl(&self, path: &Path) -> Result<(), ExportError> {
+        if !self.watertight {
+            return Err(ExportError::NotWatertight);
+        }
+        if !self.open_joints.is_empty() {
+            return Err(ExportError::OpenJoints(self.open_joints.join(", ")));
+        }
+
+        let triangle_count: usize = self.meshes.iter().map(|m| m.indices.len() / 3).sum();
+        let mut header = [0u8; 80];
+        let name = env!("CARGO_PKG_NAME").as_bytes();
+        header[..name.len()].copy_from_slice(name);
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&header)?;
+        file.write_all(&(triangle_count as u32).to_le_bytes())?;
+        for [a, b, c] in self.meshes.iter().flat_map(|m| m.triangles()) {
+            let normal = (b - a).cross(c - a).normalize_or_zero();
+            for v in [normal, a, b, c] {
+                file.write_all(&float_bytes(v.to_array().iter()))?;
+            }
+            //attribute byte count, unused
+            file.write_all(&0u16.to_le_bytes())?;
+        }
+        file.flush()?;
+
+        Ok(())
+    }
+}
+
+fn float_bytes<'a>(values: impl Iterator<Item = &'a f32>) -> Vec<u8> {
+    values.flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn pad_to_4(bytes: &mut Vec<u8>, with: u8) {
+    while bytes.len() % 4 != 0 {
+        bytes.push(with);
+    }
+}
+
+//appends 4 byte aligned bytes to the binary chunk, returns index of the view on them
+fn push_buffer_view(bin: &mut Vec<u8>, views: &mut Vec<Value>, bytes: &[u8], target: Option<u32>) -> usize {
+    pad_to_4(bin, 0);
+    let mut view = json!({ "buffer": 0, "byteOffset": bin.len(), "byteLength": bytes.len() });
+    if let Some(target) = target {
+        view["target"] = json!(target);
+    }
+    bin.extend_from_slice(bytes);
+    views.push(view);
+    views.len() - 1
+}
+
+//accessor on a new buffer view with bytes, accessor json should have everything but the view
+fn push_accessor(
+    bin: &mut Vec<u8>,
+    views: &mut Vec<Value>,
+    accessors: &mut Vec<Value>,
+    bytes: &[u8],
+    target: u32,
+    mut accessor: Value,
+) -> usize {
+    accessor["bufferView"] = json!(push_buffer_view(bin, views, bytes, Some(target)));
+    accessors.push(accessor);
+    accessors.len() - 1
+}
+
+//writes meshes of every slide as they are shown in the editor, most detailed level
+pub struct ExportSlides {
+    pub path: PathBuf,
+    pub format: ExportFormat,
+}
+
+impl Command for ExportSlides {
+    fn apply(self, world: &mut World) {
+        let slides: Vec<Vec<Entity>> = world
+            .query::<&Slide>()
+            .iter(world)
+            .map(|s| s.segments.clone())
+            .collect();
+
+        let mut scene = ExportScene {
+            watertight: world.resource::<UiState>().wall_thickness > 0.,
+            open_joints: open_joints(world, &slides),
+            ..default()
+        };
+        let mut material_ids = HashMap::<AssetId<StandardMaterial>, usize>::new();
+        let meshes = world.resource::<Assets<Mesh>>();
+        let materials = world.resource::<Assets<StandardMaterial>>();
+
+        for (slide_idx, segments) in slides.iter().enumerate() {
+            for (i, id) in segments.iter().enumerate() {
+                let (Some(rs), Some(lods)) = (world.get::<RoadSegment>(*id), world.get::<SegmentLods>(*id)) else { continue; };
+
+                let mut parts = vec![(format!("slide_{slide_idx}_segment_{i}"), *id, &lods.outer[0])];
+                if world.get::<Visibility>(rs.inner_wall).is_some_and(|v| *v != Visibility::Hidden) {
+                    parts.push((format!("slide_{slide_idx}_segment_{i}_inner"), rs.inner_wall, &lods.inner[0]));
+                }
+
+                for (name, entity, mesh_handle) in parts {
+                    let Some(mesh) = meshes.get(mesh_handle) else { continue; };
+                    let Some(material_handle) = world.get::<Handle<StandardMaterial>>(entity) else { continue; };
+                    let Some(material) = materials.get(material_handle) else { continue; };
+
+                    let material_idx = scene.material_index(&mut material_ids, material_handle.id(), material);
+
+                    scene.meshes.extend(ExportMesh::from_mesh(name, mesh, material_idx));
+                }
+            }
+        }
+
+        match scene.write(&self.path, self.format) {
+            Ok(()) => info!("exported {} meshes to {:?}", scene.meshes.len(), self.path),
+            Err(e) => error!("export to {:?} failed: {e}", self.path),
+        }
+    }
+}
+
+//exports a park, or the default slide when there is none, without starting the editor.
+//format is taken from the file extension. slides are spawned and framed by the same code the editor runs,
+//meshes are built at the most detailed level
+pub fn export_headless(path: &Path, park: Option<&Path>) -> Result<(), ExportError> {
+    let format = ExportFormat::from_path(path).ok_or_else(|| ExportError::UnknownFormat(path.to_path_buf()))?;
+    let mut app = headless_app();
+
+    match park {
+        Some(park_path) => {
+            let bytes = fs::read(park_path).map_err(ParkError::from)?;
+            let park = ParkFile::parse(&bytes)?;
+            if let Some(slide) = park.spawn(app.world_mut()).first() {
+                return Err(ExportError::SlideNotSpawned(*slide));
+            }
+        }
+        None => {
+            spawn_slide(app.world_mut(), &default_slide_positions());
+        }
+    }
+    wait_for_profiles(&mut app)?;
+
+    let world = app.world_mut();
+    world.run_system_once(update_curves);
+    world.run_system_once(update_slide_frames);
+
+    let slides: Vec<Vec<Entity>> = world
+        .query::<&Slide>()
+        .iter(world)
+        .map(|s| s.segments.clone())
+        .collect();
+    let settings = world.resource::<UiState>().clone();
+    let profiles = world.resource::<Assets<ProfileShape>>();
+    let materials = world.resource::<Assets<StandardMaterial>>();
+
+    let mut scene = ExportScene {
+        watertight: settings.wall_thickness > 0.,
+        open_joints: open_joints(world, &slides),
+        ..default()
+    };
+    let mut material_ids = HashMap::<AssetId<StandardMaterial>, usize>::new();
+
+    for (slide_idx, segments) in slides.iter().enumerate() {
+        let last = segments.len().saturating_sub(1);
+        for (i, id) in segments.iter().enumerate() {
+            let Some(rs) = world.get::<RoadSegment>(*id) else { continue; };
+            let Some(ring_profiles) = rs.ring_profiles(profiles) else { continue; };
+            let built = SegmentMeshInput {
+                segment: rs.clone(),
+                ring_profiles,
+                sections_amnt: settings.sections_amnt.max(2) as usize,
+                ring_spacing: settings.ring_spacing,
+                curve_tolerance: settings.curve_tolerance,
+                wall_thickness: settings.wall_thickness,
+                uv_tile_size: settings.uv_tile_size,
+                caps: [i == 0, i == last],
+            }
+            .build_lod(0);
+
+            let mut parts = vec![(format!("slide_{slide_idx}_segment_{i}"), *id, built.outer)];
+            if let Some(inner) = built.inner {
+                parts.push((format!("slide_{slide_idx}_segment_{i}_inner"), rs.inner_wall, inner));
+            }
+            for (name, entity, mesh) in parts {
+                let Some(material_handle) = world.get::<Handle<StandardMaterial>>(entity) else { continue; };
+                let Some(material) = materials.get(material_handle) else { continue; };
+                let material_idx = scene.material_index(&mut material_ids, material_handle.id(), material);
+                scene.meshes.extend(ExportMesh::from_mesh(name, &mesh, material_idx));
+            }
+        }
+    }
+
+    scene.write(path, format)
+}
+
+//joints of every slide whose neighbour rings do not meet, like into a segment blending to another profile
+fn open_joints(world: &World, slides: &[Vec<Entity>]) -> Vec<String> {
+    let profiles = world.resource::<Assets<ProfileShape>>();
+    let ring = |id: Entity, side: usize| {
+        let rs = world.get::<RoadSegment>(id)?;
+        Some(end_ring(rs, &rs.ring_profiles(profiles)?, side))
+    };
+
+    let mut open = Vec::new();
+    for (slide_idx, segments) in slides.iter().enumerate() {
+        for (i, pair) in segments.windows(2).enumerate() {
+            let (Some(end), Some(start)) = (ring(pair[0], 1), ring(pair[1], 0)) else { continue; };
+            if !rings_meet(&end, &start) {
+                open.push(format!("slide_{slide_idx}_segment_{i} and slide_{slide_idx}_segment_{}", i + 1));
+            }
+        }
+    }
+    open
+}
+
+//assets and settings slides need without a window or a renderer
+fn headless_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+        .init_asset::<Mesh>()
+        .init_asset::<Image>()
+        .init_asset::<StandardMaterial>()
+        .init_asset::<ProfileShape>()
+        .init_asset_loader::<ProfileShapeLoader>()
+        .init_resource::<UiState>();
+    app.finish();
+    app.cleanup();
+    app
+}
+
+//profile assets of a park load on the io pool, meshes can not be built before every one is in
+fn wait_for_profiles(app: &mut App) -> Result<(), ExportError> {
+    loop {
+        app.update();
+        let world = app.world_mut();
+        let handles: Vec<Handle<ProfileShape>> = world
+            .query::<&RoadSegment>()
+            .iter(world)
+            .flat_map(|rs| [Some(&rs.profile), rs.end_profile.as_ref()])
+            .flatten()
+            .filter_map(|profile| match profile {
+                ProfileKind::Asset(handle) => Some(handle.clone()),
+                _ => None,
+            })
+            .collect();
+
+        let asset_server = world.resource::<AssetServer>();
+        let mut loading = false;
+        for handle in handles {
+            match asset_server.load_state(handle.id()) {
+                LoadState::Loaded => {}
+                LoadState::Failed(e) => return Err(ExportError::Profile(e.to_string())),
+                _ => loading = true,
+            }
+        }
+        if !loading {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
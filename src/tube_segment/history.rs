@@ -0,0 +1,576 @@
+use bevy::{
+    ecs::world::Command,
+    prelude::*,
+    utils::HashMap,
+};
+use bevy_egui::{egui, EguiContexts};
+use super::{
+    curve_kind::CurveKind,
+    profile_shape::ProfileKind,
+    slide::{name_segments, Slide},
+    ControlPointDraggable, ControlPointState, RoadSegment,
+};
+
+//undo steps kept, older ones are forgotten
+const MAX_STEPS: usize = 200;
+
+//every edit of the scene goes into history after it is applied, so it can be undone and redone.
+//edits of the same thing done while the mouse button stays held are one step, see seal
+#[derive(Resource)]
+pub struct History {
+    done: Vec<EditorCommand>,
+    undone: Vec<EditorCommand>,
+    //true when the next edit starts a new step even if it edits the same thing as the last one
+    sealed: bool,
+    //hidden segments and points no step can bring back anymore, despawned by despawn_history_garbage
+    garbage: Vec<Entity>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            done: vec![],
+            undone: vec![],
+            sealed: true,
+            garbage: vec![],
+        }
+    }
+}
+
+impl History {
+    //command has to be applied already
+    pub fn record(&mut self, cmd: EditorCommand) {
+        for undone in std::mem::take(&mut self.undone) {
+            undone.forget(&mut self.garbage, false);
+        }
+
+        let merged = !self.sealed && self.done.last_mut().is_some_and(|last| last.merge(&cmd));
+        if !merged {
+            self.done.push(cmd);
+        }
+        self.sealed = false;
+
+        if self.done.len() > MAX_STEPS {
+            let oldest = self.done.remove(0);
+            oldest.forget(&mut self.garbage, true);
+        }
+    }
+
+    //ends the current step
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    //scene was replaced, entities of the steps are gone
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn undo(&mut self, world: &mut World) {
+        let Some(mut cmd) = self.done.pop() else { return; };
+        cmd.undo(world);
+        self.undone.push(cmd);
+        self.sealed = true;
+    }
+
+    fn redo(&mut self, world: &mut World) {
+        let Some(mut cmd) = self.undone.pop() else { return; };
+        cmd.redo(world);
+        self.done.push(cmd);
+        self.sealed = true;
+    }
+}
+
+//segment props edited from the ui
+#[derive(Clone, PartialEq)]
+pub struct SegmentProps {
+    pub curve_kind: CurveKind,
+    pub weights: [f32; 4],
+    pub profile: ProfileKind,
+    pub end_profile: Option<ProfileKind>,
+    pub blend: [f32; 2],
+    pub radius: [f32; 2],
+    pub handle_lens: [Option<f32>; 2],
+}
+
+impl SegmentProps {
+    pub fn of(rs: &RoadSegment) -> Self {
+        Self {
+            curve_kind: rs.curve_kind,
+            weights: rs.weights,
+            profile: rs.profile.clone(),
+            end_profile: rs.end_profile.clone(),
+            blend: rs.blend,
+            radius: rs.radius,
+            handle_lens: rs.handle_lens,
+        }
+    }
+
+    //touches only fields that differ, so curves and meshes are not rebuilt for nothing
+    pub fn apply_to(&self, rs: &mut Mut<RoadSegment>) {
+        if self.curve_kind != rs.curve_kind { rs.curve_kind = self.curve_kind; }
+        if self.weights != rs.weights { rs.weights = self.weights; }
+        if self.profile != rs.profile { rs.profile = self.profile.clone(); }
+        if self.end_profile != rs.end_profile { rs.end_profile = self.end_profile.clone(); }
+        if self.blend != rs.blend { rs.blend = self.blend; }
+        if self.radius != rs.radius { rs.radius = self.radius; }
+        if self.handle_lens != rs.handle_lens { rs.handle_lens = self.handle_lens; }
+    }
+}
+
+pub struct PointMove {
+    pub point: Entity,
+    pub before: Transform,
+    pub after: Transform,
+}
+
+//every segment and control point of a slide, kept before and after structural edits (split, merge, extend).
+//entities a state leaves out stay alive but hidden and without their components,
+//so undo brings back the same entities and steps recorded before still point at them
+#[derive(Clone)]
+pub struct SlideState {
+    pub segments: Vec<(Entity, RoadSegment)>,
+    //transform, bank and tangent length
+    pub points: Vec<(Entity, Transform, ControlPointDraggable)>,
+}
+
+impl SlideState {
+    pub fn capture(world: &World, slide: Entity) -> Option<Self> {
+        let segments: Vec<(Entity, RoadSegment)> = world
+            .get::<Slide>(slide)?
+            .segments
+            .iter()
+            .filter_map(|id| Some((*id, world.get::<RoadSegment>(*id)?.clone())))
+            .collect();
+
+        let mut points: Vec<(Entity, Transform, ControlPointDraggable)> = vec![];
+        for pt_id in segments.iter().flat_map(|(_, rs)| rs.pts_ids) {
+            if points.iter().any(|(id, ..)| *id == pt_id) { continue; }
+            let Some(trm) = world.get::<Transform>(pt_id) else { continue; };
+            let cp = world.get::<ControlPointDraggable>(pt_id).cloned().unwrap_or_default();
+            points.push((pt_id, *trm, ControlPointDraggable { state: ControlPointState::None, ..cp }));
+        }
+
+        Some(Self { segments, points })
+    }
+
+    //drops points no segment goes through anymore
+    pub fn retain_used_points(&mut self) {
+        let segments = &self.segments;
+        self.points.retain(|(id, ..)| segments.iter().any(|(_, rs)| rs.pts_ids.contains(id)));
+    }
+
+    fn has(&self, entity: Entity) -> bool {
+        self.segments.iter().any(|(id, _)| *id == entity) || self.points.iter().any(|(id, ..)| *id == entity)
+    }
+
+    //entities of this state the other one has not
+    fn left_out_of<'a>(&'a self, other: &'a SlideState) -> impl Iterator<Item = Entity> + 'a {
+        self.segments
+            .iter()
+            .map(|(id, _)| *id)
+            .chain(self.points.iter().map(|(id, ..)| *id))
+            .filter(|id| !other.has(*id))
+    }
+
+    //puts the slide from the other state into this one
+    pub fn restore(&self, world: &mut World, slide: Entity, from: &SlideState) {
+        let left_out: Vec<Entity> = from.left_out_of(self).collect();
+        for id in left_out {
+            if let Some(mut e) = world.get_entity_mut(id) {
+                e.remove::<(RoadSegment, ControlPointDraggable)>();
+            }
+            set_visibility(world, id, Visibility::Hidden);
+        }
+
+        for (id, trm, cp) in self.points.iter() {
+            let Some(mut e) = world.get_entity_mut(*id) else { continue; };
+            e.insert((*trm, cp.clone()));
+            set_visibility(world, *id, Visibility::Inherited);
+        }
+        for (id, rs) in self.segments.iter() {
+            let Some(mut e) = world.get_entity_mut(*id) else { continue; };
+            e.insert(rs.clone());
+            set_visibility(world, *id, Visibility::Inherited);
+        }
+
+        if let Some(mut s) = world.get_mut::<Slide>(slide) {
+            s.segments = self.segments.iter().map(|(id, _)| *id).collect();
+        }
+        name_segments(world, slide);
+    }
+}
+
+fn set_visibility(world: &mut World, entity: Entity, visibility: Visibility) {
+    if let Some(mut v) = world.get_mut::<Visibility>(entity) {
+        *v = visibility;
+    }
+}
+
+//one undo step. keeps both states of what it changed
+pub enum EditorCommand {
+    MovePoints(Vec<PointMove>),
+    SetBank { point: Entity, before: f32, after: f32 },
+    //tangent length of an end point
+    SetTangentLen { point: Entity, before: f32, after: f32 },
+    SetSegment { segment: Entity, before: SegmentProps, after: SegmentProps },
+    //entity has to have a material
+    SetColor { entity: Entity, before: Color, after: Color },
+    //segments or points added or removed
+    Restructure { name: &'static str, slide: Entity, before: SlideState, after: SlideState },
+    //undone in reverse order
+    Group(Vec<EditorCommand>),
+}
+
+impl EditorCommand {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EditorCommand::MovePoints(moves) if moves.len() == 1 => "Move point",
+            EditorCommand::MovePoints(_) => "Move points",
+            EditorCommand::SetBank { .. } => "Bank",
+            EditorCommand::SetTangentLen { .. } => "Tangent length",
+            EditorCommand::SetSegment { before, after, .. } if before.profile != after.profile
+                || before.end_profile != after.end_profile => "Change profile",
+            EditorCommand::SetSegment { before, after, .. } if before.curve_kind != after.curve_kind => "Change curve",
+            EditorCommand::SetSegment { .. } => "Change segment",
+            EditorCommand::SetColor { .. } => "Color",
+            EditorCommand::Restructure { name, .. } => *name,
+            EditorCommand::Group(cmds) => cmds.first().map_or("Edit", |c| c.name()),
+        }
+    }
+
+    fn undo(&mut self, world: &mut World) {
+        match self {
+            EditorCommand::MovePoints(moves) => {
+                for m in moves.iter() {
+                    set_transform(world, m.point, m.before);
+                }
+            }
+            EditorCommand::SetBank { point, before, .. } => set_bank(world, *point, *before),
+            EditorCommand::SetTangentLen { point, before, .. } => set_tangent_len(world, *point, *before),
+            EditorCommand::SetSegment { segment, before, .. } => set_props(world, *segment, before),
+            EditorCommand::SetColor { entity, before, .. } => set_color(world, *entity, *before),
+            EditorCommand::Restructure { slide, before, after, .. } => before.restore(world, *slide, after),
+            EditorCommand::Group(cmds) => {
+                for cmd in cmds.iter_mut().rev() {
+                    cmd.undo(world);
+                }
+            }
+        }
+    }
+
+    fn redo(&mut self, world: &mut World) {
+        match self {
+            EditorCommand::MovePoints(moves) => {
+                for m in moves.iter() {
+                    set_transform(world, m.point, m.after);
+                }
+            }
+            EditorCommand::SetBank { point, after, .. } => set_bank(world, *point, *after),
+            EditorCommand::SetTangentLen { point, after, .. } => set_tangent_len(world, *point, *after),
+            EditorCommand::SetSegment { segment, after, .. } => set_props(world, *segment, after),
+            EditorCommand::SetColor { entity, after, .. } => set_color(world, *entity, *after),
+            EditorCommand::Restructure { slide, before, after, .. } => after.restore(world, *slide, before),
+            EditorCommand::Group(cmds) => {
+                for cmd in cmds.iter_mut() {
+                    cmd.redo(world);
+                }
+            }
+        }
+    }
+
+    //takes the next edit in if it continues this one
+    fn merge(&mut self, next: &EditorCommand) -> bool {
+        match (self, next) {
+            (
+                EditorCommand::MovePoints(moves),
+                EditorCommand::MovePoints(next_moves),
+            ) if moves.len() == next_moves.len() && moves.iter().zip(next_moves).all(|(m, n)| m.point == n.point) => {
+                for (m, n) in moves.iter_mut().zip(next_moves) {
+                    m.after = n.after;
+                }
+            }
+            (
+                EditorCommand::SetBank { point, after, .. },
+                EditorCommand::SetBank { point: next_point, after: next_after, .. },
+            ) if point == next_point => *after = *next_after,
+            (
+                EditorCommand::SetTangentLen { point, after, .. },
+                EditorCommand::SetTangentLen { point: next_point, after: next_after, .. },
+            ) if point == next_point => *after = *next_after,
+            (
+                EditorCommand::SetSegment { segment, after, .. },
+                EditorCommand::SetSegment { segment: next_segment, after: next_after, .. },
+            ) if segment == next_segment => *after = next_after.clone(),
+            (
+                EditorCommand::SetColor { entity, after, .. },
+                EditorCommand::SetColor { entity: next_entity, after: next_after, .. },
+            ) if entity == next_entity => *after = *next_after,
+            _ => return false,
+        }
+        true
+    }
+
+    //step is dropped from history, entities it keeps hidden can not come back.
+    //done steps keep out what they removed, undone ones what they added
+    fn forget(self, garbage: &mut Vec<Entity>, done: bool) {
+        match self {
+            EditorCommand::Restructure { before, after, .. } => {
+                let (gone, current) = if done { (&before, &after) } else { (&after, &before) };
+                garbage.extend(gone.left_out_of(current));
+            }
+            EditorCommand::Group(cmds) => {
+                for cmd in cmds {
+                    cmd.forget(garbage, done);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn set_transform(world: &mut World, entity: Entity, transform: Transform) {
+    if let Some(mut trm) = world.get_mut::<Transform>(entity) {
+        *trm = transform;
+    }
+}
+
+fn set_bank(world: &mut World, point: Entity, bank: f32) {
+    if let Some(mut cp) = world.get_mut::<ControlPointDraggable>(point) {
+        cp.bank = bank;
+    }
+}
+
+fn set_tangent_len(world: &mut World, point: Entity, len: f32) {
+    if let Some(mut cp) = world.get_mut::<ControlPointDraggable>(point) {
+        cp.tangent_len = len;
+    }
+}
+
+fn set_props(world: &mut World, segment: Entity, props: &SegmentProps) {
+    if let Some(mut rs) = world.get_mut::<RoadSegment>(segment) {
+        props.apply_to(&mut rs);
+    }
+}
+
+fn set_color(world: &mut World, entity: Entity, color: Color) {
+    let Some(handle) = world.get::<Handle<StandardMaterial>>(entity).cloned() else { return; };
+    if let Some(material) = world.resource_mut::<Assets<StandardMaterial>>().get_mut(&handle) {
+        material.base_color = color;
+    }
+}
+
+pub struct Undo {
+    pub steps: usize,
+}
+
+impl Command for Undo {
+    fn apply(self, world: &mut World) {
+        world.resource_scope(|world, mut history: Mut<History>| {
+            for _ in 0..self.steps {
+                history.undo(world);
+            }
+        });
+    }
+}
+
+pub struct Redo {
+    pub steps: usize,
+}
+
+impl Command for Redo {
+    fn apply(self, world: &mut World) {
+        world.resource_scope(|world, mut history: Mut<History>| {
+            for _ in 0..self.steps {
+                history.redo(world);
+            }
+        });
+    }
+}
+
+//one drag is one step: transforms are remembered when a drag starts and recorded when it ends.
+//points of a selection dragged together are one step, and so are end points turned by their dragged tangent handles.
+//a dragged handle also sets the tangent length of its end point, that is recorded with the step too.
+//runs between update_selection and update_positions: on the press frame points are remembered
+//before the cursor or snapping moves them, on the release frame nothing moves them anymore
+pub fn record_drags(
+    mut history: ResMut<History>,
+    //transform and tangent length
+    mut drag_starts: Local<HashMap<Entity, (Transform, f32)>>,
+    //segments with own handle lengths change with their handles
+    mut segment_starts: Local<HashMap<Entity, SegmentProps>>,
+    control_pts: Query<(Entity, &Transform, &ControlPointDraggable)>,
+    road_segments: Query<(Entity, &RoadSegment)>,
+) {
+    let dragged: Vec<Entity> = control_pts
+        .iter()
+        .filter(|(_, _, cp)| cp.state == ControlPointState::Drag)
+        .map(|(id, _, _)| id)
+        .collect();
+
+    if !dragged.is_empty() {
+        let mut touched = dragged.clone();
+        for (id, rs) in road_segments.iter() {
+            for (anchor, _) in dragged.iter().filter_map(|pt| rs.handle_anchor(*pt)) {
+                touched.push(anchor);
+                segment_starts.entry(id).or_insert_with(|| SegmentProps::of(rs));
+            }
+        }
+        for id in touched {
+            if let Ok((_, trm, cp)) = control_pts.get(id) {
+                drag_starts.entry(id).or_insert((*trm, cp.tangent_len));
+            }
+        }
+        return;
+    }
+
+    let mut moves: Vec<PointMove> = vec![];
+    let mut tangent_changes: Vec<EditorCommand> = vec![];
+    for (point, (before, len_before)) in drag_starts.drain() {
+        //detached or despawned while dragged
+        let Ok((_, after, cp)) = control_pts.get(point) else { continue; };
+        if before != *after {
+            moves.push(PointMove { point, before, after: *after });
+        }
+        if len_before != cp.tangent_len {
+            tangent_changes.push(EditorCommand::SetTangentLen { point, before: len_before, after: cp.tangent_len });
+        }
+    }
+    let segment_changes = segment_starts.drain().filter_map(|(segment, before)| {
+        let (_, rs) = road_segments.get(segment).ok()?;
+        let after = SegmentProps::of(rs);
+        (before != after).then_some(EditorCommand::SetSegment { segment, before, after })
+    });
+
+    let mut cmds: Vec<EditorCommand> = (!moves.is_empty())
+        .then(|| EditorCommand::MovePoints(moves))
+        .into_iter()
+        .chain(tangent_changes)
+        .chain(segment_changes)
+        .collect();
+    let cmd = match cmds.len() {
+        0 => return,
+        1 => cmds.remove(0),
+        _ => EditorCommand::Group(cmds),
+    };
+    history.record(cmd);
+    history.seal();
+}
+
+//ctrl+z undoes, ctrl+shift+z redoes
+pub fn history_hotkeys(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    keys: Res<ButtonInput<KeyCode>>,
+) {
+    //text fields have their own undo
+    if contexts.ctx_mut().wants_keyboard_input() { return; }
+
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    if !ctrl || !keys.just_pressed(KeyCode::KeyZ) { return; }
+
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        commands.add(Redo { steps: 1 });
+    } else {
+        commands.add(Undo { steps: 1 });
+    }
+}
+
+//list of steps, clicking one goes back or forward to the state right after it
+pub fn history_ui(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut history: ResMut<History>,
+) {
+    let ctx = contexts.ctx_mut();
+    //ui edits made while the button is held are one step, e.g. dragging a value
+    if !ctx.input(|i| i.pointer.any_down()) {
+        history.seal();
+    }
+
+    egui::Window::new("History").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            if ui.add_enabled(history.can_undo(), egui::Button::new("Undo")).clicked() {
+                commands.add(Undo { steps: 1 });
+            }
+            if ui.add_enabled(history.can_redo(), egui::Button::new("Redo")).clicked() {
+                commands.add(Redo { steps: 1 });
+            }
+        });
+        ui.separator();
+
+        egui::ScrollArea::vertical().max_height(240.).show(ui, |ui| {
+            let done = history.done.len();
+            if ui.selectable_label(done == 0, "Start").clicked() {
+                commands.add(Undo { steps: done });
+            }
+            for (i, cmd) in history.done.iter().enumerate() {
+                if ui.selectable_label(i + 1 == done, cmd.name()).clicked() {
+                    commands.add(Undo { steps: done - i - 1 });
+                }
+            }
+            for (i, cmd) in history.undone.iter().rev().enumerate() {
+                let label = egui::SelectableLabel::new(false, egui::RichText::new(cmd.name()).weak());
+                if ui.add(label).clicked() {
+                    commands.add(Redo { steps: i + 1 });
+                }
+            }
+        });
+    });
+}
+
+pub fn despawn_history_garbage(
+    mut commands: Commands,
+    mut history: ResMut<History>,
+) {
+    if history.garbage.is_empty() { return; }
+    for entity in history.garbage.drain(..) {
+        if let Some(e) = commands.get_entity(entity) {
+            e.despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //stands in for update_positions snapping the pressed point right away
+    fn snap_dragged(mut points: Query<(&mut Transform, &ControlPointDraggable)>) {
+        for (mut trm, cp) in points.iter_mut() {
+            if cp.state == ControlPointState::Drag {
+                trm.translation = trm.translation.round() + Vec3::Y;
+            }
+        }
+    }
+
+    #[test]
+    fn undo_of_drag_goes_back_to_where_the_point_was_pressed() {
+        let mut world = World::new();
+        world.init_resource::<History>();
+        let pressed_at = Transform::from_xyz(0.3, 0.4, -2.6).looking_to(Vec3::NEG_Z, Vec3::Y);
+        let point = world.spawn((pressed_at, ControlPointDraggable::default())).id();
+
+        //same order as in the plugin
+        let mut schedule = Schedule::default();
+        schedule.add_systems((record_drags, snap_dragged).chain());
+
+        world.get_mut::<ControlPointDraggable>(point).unwrap().state = ControlPointState::Drag;
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        world.get_mut::<ControlPointDraggable>(point).unwrap().state = ControlPointState::None;
+        schedule.run(&mut world);
+
+        assert_ne!(*world.get::<Transform>(point).unwrap(), pressed_at);
+        Undo { steps: 1 }.apply(&mut world);
+        assert_eq!(*world.get::<Transform>(point).unwrap(), pressed_at);
+    }
+}
@@ -1,4 +1,4 @@
-use bevy::{math::{Quat, Vec3}, prelude::Vec2};
+use bevy::{math::{Mat3, Quat, Vec3}, prelude::Vec2};
 
 #[derive(Clone, Copy)]
 pub struct OrientedPoint {
@@ -7,13 +7,31 @@ pub struct OrientedPoint {
 }
 
 impl OrientedPoint {
-    pub fn from_forward(pos: Vec3, forward: Vec3) -> Self {
+    //local z looks to forward, local y as close to up as possible
+    pub fn from_forward_up(pos: Vec3, forward: Vec3, up: Vec3) -> Self {
+        let forward = forward.normalize();
+        let right = up.cross(forward).try_normalize()
+            .unwrap_or_else(|| forward.any_orthonormal_vector());
+        let up = forward.cross(right);
+
         Self {
             pos,
-            rot: Quat::from_rotation_arc(Vec3::Z, forward)
+            rot: Quat::from_mat3(&Mat3::from_cols(right, up, forward))
         }
     }
 
+    //rotated around its own forward
+    pub fn rolled(self, angle: f32) -> Self {
+        Self {
+            pos: self.pos,
+            rot: self.rot * Quat::from_rotation_z(angle)
+        }
+    }
+
+    pub fn up(self) -> Vec3 {
+        self.rot * Vec3::Y
+    }
+
     pub fn local_to_world_pos(self, local_space_pos: Vec2) -> Vec3 {
         let world_pos = self.rot * local_space_pos.extend(0.);
         self.pos + world_pos
@@ -23,4 +41,108 @@ impl OrientedPoint {
         self.rot * local_space_pos.extend(0.)
     }
 }
-   
\ No newline at end of file
+
+//carries up vector from one curve sample to the next one with the least possible twist.
+//double reflection method: https://www.microsoft.com/en-us/research/wp-content/uploads/2016/12/Computation-of-rotation-minimizing-frames.pdf
+//tangents should be normalized
+pub fn rotation_minimizing_up(
+    from_pos: Vec3, 
+    from_tangent: Vec3, 
+    from_up: Vec3, 
+    to_pos: Vec3, 
+    to_tangent: Vec3,
+) -> Vec3 {
+    //reflect up and tangent in the plane between two samples
+    let v1 = to_pos - from_pos;
+    let c1 = v1.length_squared();
+    let (up_l, tangent_l) = if c1 > f32::EPSILON {
+        (
+            from_up - (2. / c1) * v1.dot(from_up) * v1,
+            from_tangent - (2. / c1) * v1.dot(from_tangent) * v1,
+        )
+    } else {
+        (from_up, from_tangent)
+    };
+
+    //reflect once more so reflected tangent matches the next one
+    let v2 = to_tangent - tangent_l;
+    let c2 = v2.length_squared();
+    let up = if c2 > f32::EPSILON {
+        up_l - (2. / c2) * v2.dot(up_l) * v2
+    } else {
+        up_l
+    };
+
+    //keep it perpendicular to the tangent, float errors pile up along long slides
+    (up - to_tangent * up.dot(to_tangent))
+        .try_normalize()
+        .unwrap_or_else(|| to_tangent.any_orthonormal_vector())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+    use super::*;
+
+    const EPS: f32 = 1e-4;
+
+    //up carried along samples of a curve from the up at its start
+    fn carry(samples: &[(Vec3, Vec3)], up: Vec3) -> Vec<Vec3> {
+        let mut ups = vec![up];
+        for pair in samples.windows(2) {
+            let ((from_pos, from_tangent), (to_pos, to_tangent)) = (pair[0], pair[1]);
+            let prev = *ups.last().unwrap();
+            ups.push(rotation_minimizing_up(from_pos, from_tangent, prev, to_pos, to_tangent));
+        }
+        ups
+    }
+
+    #[test]
+    fn straight_line_keeps_up() {
+        let samples: Vec<(Vec3, Vec3)> = (0..10).map(|i| (Vec3::X * i as f32, Vec3::X)).collect();
+        for up in carry(&samples, Vec3::Y) {
+            assert!(up.distance(Vec3::Y) < EPS);
+        }
+    }
+
+    #[test]
+    fn flat_turn_keeps_up() {
+        //circle in the ground plane, up stays world up all the way round
+        let samples: Vec<(Vec3, Vec3)> = (0..=64)
+            .map(|i| {
+                let a = TAU * i as f32 / 64.;
+                (Vec3::new(a.cos(), 0., a.sin()), Vec3::new(-a.sin(), 0., a.cos()))
+            })
+            .collect();
+        for up in carry(&samples, Vec3::Y) {
+            assert!(up.distance(Vec3::Y) < EPS);
+        }
+    }
+
+    #[test]
+    fn up_is_unit_and_across_the_tangent() {
+        //helix climbing around y
+        let samples: Vec<(Vec3, Vec3)> = (0..=100)
+            .map(|i| {
+                let a = TAU * i as f32 / 50.;
+                let pos = Vec3::new(a.cos(), a * 0.3, a.sin());
+                let tangent = Vec3::new(-a.sin(), 0.3, a.cos()).normalize();
+                (pos, tangent)
+            })
+            .collect();
+        let start_up = (Vec3::Y - samples[0].1 * samples[0].1.y).normalize();
+        for (up, (_, tangent)) in carry(&samples, start_up).into_iter().zip(samples.iter()) {
+            assert!((up.length() - 1.).abs() < EPS);
+            assert!(up.dot(*tangent).abs() < EPS);
+        }
+    }
+
+    #[test]
+    fn frame_looks_forward_with_up() {
+        let op = OrientedPoint::from_forward_up(Vec3::ZERO, Vec3::new(0., 0., 2.), Vec3::Y);
+        assert!((op.rot * Vec3::Z).distance(Vec3::Z) < EPS);
+        assert!(op.up().distance(Vec3::Y) < EPS);
+        //rolled a quarter turn, up goes to the side
+        assert!(op.rolled(TAU / 4.).up().dot(Vec3::Y).abs() < EPS);
+    }
+}
@@ -0,0 +1,510 @@
+use std::{fs, path::PathBuf};
+use bevy::{
+    asset::ron::{self, ser::PrettyConfig},
+    ecs::world::Command,
+    prelude::*,
+};
+use bevy_panorbit_camera::PanOrbitCamera;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::my_ui::{RingSpacing, UiState};
+use super::{
+    curve_kind::CurveKind,
+    history::History,
+    selection::Selection,
+    profile_shape::ProfileKind,
+    slide::{spawn_slide, Slide},
+    ControlPointDraggable, RoadSegment,
+};
+
+//whole park design: every slide with its segments and control points, and the camera view.
+//
+//bump PARK_VERSION when the format changes. keep the struct of the old version as ParkFileV<n>
+//and convert it to the next one in ParkFile::parse, so old designs still load
+pub const PARK_VERSION: u32 = 2;
+pub const PARKS_DIR: &str = "parks";
+
+#[derive(Serialize, Deserialize)]
+pub struct ParkFile {
+    pub version: u32,
+    pub camera: Option<CameraFile>,
+    pub slides: Vec<SlideFile>,
+    //None keeps the settings the editor has
+    #[serde(default)]
+    pub mesh: Option<MeshSettingsFile>,
+}
+
+//ui settings meshes of the park are built with
+#[derive(Serialize, Deserialize)]
+pub struct MeshSettingsFile {
+    pub sections_amnt: i32,
+    pub ring_spacing: RingSpacing,
+    pub wall_thickness: f32,
+    pub uv_tile_size: f32,
+    pub curve_tolerance: f32,
+}
+
+impl MeshSettingsFile {
+    fn new(ui_state: &UiState) -> Self {
+        Self {
+            sections_amnt: ui_state.sections_amnt,
+            ring_spacing: ui_state.ring_spacing,
+            wall_thickness: ui_state.wall_thickness,
+            uv_tile_size: ui_state.uv_tile_size,
+            curve_tolerance: ui_state.curve_tolerance,
+        }
+    }
+
+    pub fn apply_to(&self, ui_state: &mut UiState) {
+        ui_state.sections_amnt = self.sections_amnt;
+        ui_state.ring_spacing = self.ring_spacing;
+        ui_state.wall_thickness = self.wall_thickness;
+        ui_state.uv_tile_size = self.uv_tile_size;
+        ui_state.curve_tolerance = self.curve_tolerance;
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CameraFile {
+    pub focus: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub radius: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SlideFile {
+    //3 * segments + 1, like SpawnSlide takes them
+    pub points: Vec<ControlPointFile>,
+    pub segments: Vec<SegmentFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ControlPointFile {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    //radians
+    #[serde(default)]
+    pub bank: f32,
+    //see ControlPointDraggable::tangent_len
+    pub tangent_len: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SegmentFile {
+    pub curve_kind: CurveKind,
+    pub weights: [f32; 4],
+    pub profile: ProfileFile,
+    #[serde(default)]
+    pub end_profile: Option<ProfileFile>,
+    //see RoadSegment::blend
+    #[serde(default = "full_blend")]
+    pub blend: [f32; 2],
+    pub radius: [f32; 2],
+    //srgba
+    pub color: [f32; 4],
+    pub inner_color: [f32; 4],
+    //own tangent lengths at the start and end, see RoadSegment::handle_lens
+    #[serde(default)]
+    pub handle_lens: [Option<f32>; 2],
+}
+
+fn full_blend() -> [f32; 2] {
+    [0., 1.]
+}
+
+//ProfileKind without asset handles
+#[derive(Serialize, Deserialize)]
+pub enum ProfileFile {
+    Circle { sides: usize },
+    HalfPipe { sides: usize, opening: f32 },
+    Flume { width: f32, height: f32 },
+    RaftChannel { width: f32, depth: f32 },
+    //path inside assets
+    Asset(String),
+}
+
+impl ProfileFile {
+    //None for an asset not loaded from a file, it could not be loaded back
+    fn from_kind(kind: &ProfileKind) -> Option<Self> {
+        Some(match kind {
+            ProfileKind::Circle { sides } => ProfileFile::Circle { sides: *sides },
+            ProfileKind::HalfPipe { sides, opening } => ProfileFile::HalfPipe { sides: *sides, opening: *opening },
+            ProfileKind::Flume { width, height } => ProfileFile::Flume { width: *width, height: *height },
+            ProfileKind::RaftChannel { width, depth } => ProfileFile::RaftChannel { width: *width, depth: *depth },
+            ProfileKind::Asset(handle) => ProfileFile::Asset(handle.path()?.to_string()),
+        })
+    }
+
+    fn to_kind(&self, asset_server: &AssetServer) -> ProfileKind {
+        match self {
+            ProfileFile::Circle { sides } => ProfileKind::Circle { sides: *sides },
+            ProfileFile::HalfPipe { sides, opening } => ProfileKind::HalfPipe { sides: *sides, opening: *opening },
+            ProfileFile::Flume { width, height } => ProfileKind::Flume { width: *width, height: *height },
+            ProfileFile::RaftChannel { width, depth } => ProfileKind::RaftChannel { width: *width, depth: *depth },
+            ProfileFile::Asset(path) => ProfileKind::Asset(asset_server.load(path.clone())),
+        }
+    }
+}
+
+impl ControlPointFile {
+    fn new(trm: &Transform, cp: &ControlPointDraggable) -> Self {
+        Self {
+            translation: trm.translation.to_array(),
+            rotation: trm.rotation.to_array(),
+            bank: cp.bank,
+            tangent_len: cp.tangent_len,
+        }
+    }
+
+    fn transform(&self) -> Transform {
+        Transform {
+            translation: Vec3::from_array(self.translation),
+            rotation: Quat::from_array(self.rotation).normalize(),
+            ..default()
+        }
+    }
+}
+
+//version 1: tangent length of a point was the z scale of its transform, there were no mesh settings
+#[derive(Deserialize)]
+struct ParkFileV1 {
+    camera: Option<CameraFile>,
+    slides: Vec<SlideFileV1>,
+}
+
+#[derive(Deserialize)]
+struct SlideFileV1 {
+    points: Vec<ControlPointFileV1>,
+    segments: Vec<SegmentFile>,
+}
+
+#[derive(Deserialize)]
+struct ControlPointFileV1 {
+    translation: [f32; 3],
+    rotation: [f32; 4],
+    scale: [f32; 3],
+    #[serde(default)]
+    bank: f32,
+}
+
+impl ParkFileV1 {
+    fn migrate(self) -> ParkFile {
+        let slides = self.slides
+            .into_iter()
+            .map(|slide| SlideFile {
+                points: slide.points
+                    .into_iter()
+                    .map(|p| ControlPointFile {
+                        translation: p.translation,
+                        rotation: p.rotation,
+                        bank: p.bank,
+                        tangent_len: p.scale[2],
+                    })
+                    .collect(),
+                segments: slide.segments,
+            })
+            .collect();
+
+        ParkFile { version: 2, camera: self.camera, slides, mesh: None }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ParkError {
+    #[error("could not access park file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse park file: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("could not write park file: {0}")]
+    Write(#[from] ron::Error),
+    #[error("park file version {0} is not supported, this editor reads up to version {PARK_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("slide {slide} has {points} points for {segments} segments, expected 3 * segments + 1")]
+    PointsMismatch { slide: usize, points: usize, segments: usize },
+    #[error("slide {slide} has no segments")]
+    EmptySlide { slide: usize },
+    #[error("segment {segment} of slide {slide} has a profile that is not from a file, it could not be loaded back")]
+    UnsavedProfile { slide: usize, segment: usize },
+}
+
+//only the version, read first to pick the struct the rest of the file is parsed with
+#[derive(Deserialize)]
+struct Versioned {
+    version: u32,
+}
+
+impl ParkFile {
+    pub fn parse(bytes: &[u8]) -> Result<Self, ParkError> {
+        let Versioned { version } = ron::de::from_bytes(bytes)?;
+        let park: ParkFile = match version {
+            PARK_VERSION => ron::de::from_bytes(bytes)?,
+            1 => ron::de::from_bytes::<ParkFileV1>(bytes)?.migrate(),
+            v => return Err(ParkError::UnsupportedVersion(v)),
+        };
+
+        for (i, slide) in park.slides.iter().enumerate() {
+            if slide.segments.is_empty() {
+                return Err(ParkError::EmptySlide { slide: i });
+            }
+            if slide.points.len() != slide.segments.len() * 3 + 1 {
+                return Err(ParkError::PointsMismatch {
+                    slide: i,
+                    points: slide.points.len(),
+                    segments: slide.segments.len(),
+                });
+            }
+        }
+
+        Ok(park)
+    }
+
+    pub fn to_ron(&self) -> Result<String, ParkError> {
+        Ok(ron::ser::to_string_pretty(self, PrettyConfig::default())?)
+    }
+
+    //reads slides, camera and mesh settings of the current scene
+    fn from_world(world: &mut World) -> Result<Self, ParkError> {
+        let slides: Vec<Vec<Entity>> = world
+            .query::<&Slide>()
+            .iter(world)
+            .map(|s| s.segments.clone())
+            .collect();
+        let camera = world
+            .query::<&PanOrbitCamera>()
+            .get_single(world)
+            .ok()
+            .map(|cam| CameraFile {
+                focus: cam.target_focus.to_array(),
+                yaw: cam.target_yaw,
+                pitch: cam.target_pitch,
+                radius: cam.target_radius,
+            });
+
+        let materials = world.resource::<Assets<StandardMaterial>>();
+        let color_of = |entity: Entity| {
+            world
+                .get::<Handle<StandardMaterial>>(entity)
+                .and_then(|h| materials.get(h))
+                .map_or([1.; 4], |m| m.base_color.to_srgba().to_f32_array())
+        };
+
+        let mesh = world.get_resource::<UiState>().map(MeshSettingsFile::new);
+
+        let slides = slides
+            .iter()
+            .enumerate()
+            .map(|(slide_idx, segments)| {
+                let mut slide = SlideFile { points: vec![], segments: vec![] };
+                for (i, id) in segments.iter().enumerate() {
+                    let Some(rs) = world.get::<RoadSegment>(*id) else { continue; };
+
+                    //joints are shared, start of every next segment is the end of the previous one
+                    let pts = if i == 0 { &rs.pts_ids[..] } else { &rs.pts_ids[1..] };
+                    for pt_id in pts {
+                        let trm = world.get::<Transform>(*pt_id).copied().unwrap_or_default();
+                        let cp = world.get::<ControlPointDraggable>(*pt_id).cloned().unwrap_or_default();
+                        slide.points.push(ControlPointFile::new(&trm, &cp));
+                    }
+
+                    let unsaved = || ParkError::UnsavedProfile { slide: slide_idx, segment: i };
+                    let profile = ProfileFile::from_kind(&rs.profile).ok_or_else(unsaved)?;
+                    let end_profile = rs.end_profile
+                        .as_ref()
+                        .map(|end| ProfileFile::from_kind(end).ok_or_else(unsaved))
+                        .transpose()?;
+                    slide.segments.push(SegmentFile {
+                        curve_kind: rs.curve_kind,
+                        weights: rs.weights,
+                        profile,
+                        end_profile,
+                        blend: rs.blend,
+                        radius: rs.radius,
+                        color: color_of(*id),
+                        inner_color: color_of(rs.inner_wall),
+                        handle_lens: rs.handle_lens,
+                    });
+                }
+                Ok(slide)
+            })
+            .collect::<Result<_, ParkError>>()?;
+
+        Ok(Self { version: PARK_VERSION, camera, slides, mesh })
+    }
+
+    //replaces every slide of the scene with the ones of this park.
+    //returns indices of slides that could not be spawned, the rest of the park is spawned without them
+    pub fn spawn(&self, world: &mut World) -> Vec<usize> {
+        let mut rejected = vec![];
+        let old_slides: Vec<Entity> = world.query_filtered::<Entity, With<Slide>>().iter(world).collect();
+        for slide in old_slides {
+            world.entity_mut(slide).despawn_recursive();
+        }
+
+        if let (Some(mesh), Some(mut ui_state)) = (&self.mesh, world.get_resource_mut::<UiState>()) {
+            mesh.apply_to(&mut ui_state);
+        }
+
+        for (slide_idx, slide_file) in self.slides.iter().enumerate() {
+            let positions: Vec<Vec3> = slide_file.points.iter().map(|p| Vec3::from_array(p.translation)).collect();
+            let Some(slide) = spawn_slide(world, &positions) else {
+                rejected.push(slide_idx);
+                continue;
+            };
+            let segments = world.get::<Slide>(slide).map(|s| s.segments.clone()).unwrap_or_default();
+
+            for (i, (id, seg_file)) in segments.iter().zip(slide_file.segments.iter()).enumerate() {
+                let Some(pts_ids) = world.get::<RoadSegment>(*id).map(|rs| rs.pts_ids) else { continue; };
+
+                for (pt_id, pt_file) in pts_ids.iter().zip(slide_file.points[i * 3..].iter()) {
+                    if let Some(mut trm) = world.get_mut::<Transform>(*pt_id) {
+                        *trm = pt_file.transform();
+                    }
+                    if let Some(mut cp) = world.get_mut::<ControlPointDraggable>(*pt_id) {
+                        cp.bank = pt_file.bank;
+                        cp.tangent_len = pt_file.tangent_len;
+                    }
+                }
+
+                let asset_server = world.resource::<AssetServer>().clone();
+                let Some(mut rs) = world.get_mut::<RoadSegment>(*id) else { continue; };
+                rs.curve_kind = seg_file.curve_kind;
+                rs.weights = seg_file.weights;
+                rs.profile = seg_file.profile.to_kind(&asset_server);
+                rs.end_profile = seg_file.end_profile.as_ref().map(|p| p.to_kind(&asset_server));
+                rs.blend = seg_file.blend;
+                rs.radius = seg_file.radius;
+                rs.handle_lens = seg_file.handle_lens;
+                let inner_wall = rs.inner_wall;
+
+                for (entity, color) in [(*id, seg_file.color), (inner_wall, seg_file.inner_color)] {
+                    let Some(handle) = world.get::<Handle<StandardMaterial>>(entity).cloned() else { continue; };
+                    if let Some(material) = world.resource_mut::<Assets<StandardMaterial>>().get_mut(&handle) {
+                        material.base_color = Color::srgba(color[0], color[1], color[2], color[3]);
+                    }
+                }
+            }
+        }
+
+        if let Some(cam_file) = &self.camera {
+            if let Ok(mut cam) = world.query::<&mut PanOrbitCamera>().get_single_mut(world) {
+                cam.target_focus = Vec3::from_array(cam_file.focus);
+                cam.target_yaw = cam_file.yaw;
+                cam.target_pitch = cam_file.pitch;
+                cam.target_radius = cam_file.radius;
+            }
+        }
+
+        rejected
+    }
+}
+
+pub struct SavePark {
+    pub path: PathBuf,
+}
+
+impl Command for SavePark {
+    fn apply(self, world: &mut World) {
+        let result = ParkFile::from_world(world).and_then(|park| {
+            let text = park.to_ron()?;
+            if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&self.path, text)?;
+            Ok(park.slides.len())
+        });
+
+        match result {
+            Ok(slides) => info!("saved park with {slides} slides to {:?}", self.path),
+            Err(e) => error!("saving park to {:?} failed: {e}", self.path),
+        }
+    }
+}
+
+pub struct LoadPark {
+    pub path: PathBuf,
+}
+
+impl Command for LoadPark {
+    fn apply(self, world: &mut World) {
+        let park = fs::read(&self.path)
+            .map_err(ParkError::from)
+            .and_then(|bytes| ParkFile::parse(&bytes));
+
+        match park {
+            Ok(park) => {
+                for slide in park.spawn(world) {
+                    error!("slide {slide} of {:?} could not be spawned, it is left out", self.path);
+                }
+                //steps and selection point at the entities of the old slides
+                world.resource_mut::<History>().clear();
+                world.resource_mut::<Selection>().clear();
+                info!("loaded park with {} slides from {:?}", park.slides.len(), self.path);
+            }
+            //scene is kept as it is when the file is broken
+            Err(e) => error!("loading park from {:?} failed: {e}", self.path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1_PARK: &str = r#"(
+        version: 1,
+        camera: Some((focus: (0.0, 0.0, 0.0), yaw: 0.5, pitch: 0.3, radius: 40.0)),
+        slides: [(
+            points: [
+                (translation: (0.0, 0.0, 0.0), rotation: (0.0, 0.0, 0.0, 1.0), scale: (1.0, 1.0, 3.0), bank: 0.2),
+                (translation: (0.0, 0.0, -3.0), rotation: (0.0, 0.0, 0.0, 1.0), scale: (1.0, 1.0, 1.0)),
+                (translation: (10.0, 0.0, -7.0), rotation: (0.0, 0.0, 0.0, 1.0), scale: (1.0, 1.0, 1.0)),
+                (translation: (10.0, 0.0, -10.0), rotation: (0.0, 0.0, 0.0, 1.0), scale: (1.0, 1.0, 2.5)),
+            ],
+            segments: [(
+                curve_kind: Bezier,
+                weights: (1.0, 1.0, 1.0, 1.0),
+                profile: Circle(sides: 16),
+                radius: (1.0, 1.5),
+                color: (0.0, 1.0, 1.0, 1.0),
+                inner_color: (0.15, 0.45, 0.8, 1.0),
+            )],
+        )],
+    )"#;
+
+    #[test]
+    fn version_1_migrates_z_scale_to_tangent_length() {
+        let park = ParkFile::parse(V1_PARK.as_bytes()).unwrap();
+        assert_eq!(park.version, PARK_VERSION);
+        assert!(park.mesh.is_none());
+
+        let slide = &park.slides[0];
+        let lens: Vec<f32> = slide.points.iter().map(|p| p.tangent_len).collect();
+        assert_eq!(lens, [3., 1., 1., 2.5]);
+        assert_eq!(slide.points[0].bank, 0.2);
+        assert_eq!(slide.segments[0].blend, [0., 1.]);
+        assert_eq!(slide.segments[0].handle_lens, [None, None]);
+    }
+
+    #[test]
+    fn saved_park_parses_back() {
+        let park = ParkFile::parse(V1_PARK.as_bytes()).unwrap();
+        let again = ParkFile::parse(park.to_ron().unwrap().as_bytes()).unwrap();
+        assert_eq!(again.slides[0].points.len(), 4);
+        assert_eq!(again.slides[0].points[3].tangent_len, 2.5);
+    }
+
+    #[test]
+    fn newer_version_is_refused() {
+        let text = format!("(version: {}, camera: None, slides: [])", PARK_VERSION + 1);
+        assert!(matches!(ParkFile::parse(text.as_bytes()), Err(ParkError::UnsupportedVersion(_))));
+    }
+
+    #[test]
+    fn slide_without_segments_is_refused() {
+        let text = format!(
+            "(version: {PARK_VERSION}, camera: None, slides: [(points: [(translation: (0.0, 0.0, 0.0), rotation: (0.0, 0.0, 0.0, 1.0), tangent_len: 1.0)], segments: [])])"
+        );
+        assert!(matches!(ParkFile::parse(text.as_bytes()), Err(ParkError::EmptySlide { slide: 0 })));
+    }
+}
@@ -0,0 +1,308 @@
+use std::{fs, path::{Path, PathBuf}};
+use bevy::{
+    asset::{io::Reader, ron::{self, ser::PrettyConfig}, AssetLoader, AsyncReadExt, LoadContext, LoadedFolder},
+    ecs::world::Command,
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use super::{
+    export::ASSETS_DIR,
+    profile_shape::{ProfileKind, ProfileShape, Vertex},
+};
+
+//profiles designed by artists, every *.profile.ron from assets/profiles
+//
+//(
+//    vertices: [
+//        (point: (0.0, 1.0), normal: (0.0, 1.0), u: 0.0),
+//        ...
+//    ],
+//    line_indices: [0, 1, 1, 2, ...],
+//)
+//
+//or just an outline going counter-clockwise, normals and u's are made from it.
+//points listed in hard get sharp edges, others are smooth
+//
+//(
+//    outline: Some((
+//        points: [(-1.0, 1.0), (-1.0, -1.0), (1.0, -1.0), (1.0, 1.0)],
+//        closed: false,
+//        hard: [1, 2],
+//    )),
+//)
+//
+//presets can be saved as profile files to start a new design from, see SaveProfile
+pub const PROFILES_FOLDER: &str = "profiles";
+
+#[derive(Resource)]
+pub struct ProfileLibrary {
+    pub folder: Handle<LoadedFolder>,
+}
+
+impl ProfileLibrary {
+    //(file name, handle) of every loaded profile
+    pub fn profiles(&self, folders: &Assets<LoadedFolder>) -> Vec<(String, Handle<ProfileShape>)> {
+        let Some(folder) = folders.get(&self.folder) else { return vec![]; };
+
+        folder.handles
+            .iter()
+            .filter_map(|h| h.clone().try_typed::<ProfileShape>().ok())
+            .map(|h| (profile_name(&h), h))
+            .collect()
+    }
+}
+
+pub fn profile_name(handle: &Handle<ProfileShape>) -> String {
+    handle
+        .path()
+        .and_then(|p| p.path().file_name())
+        .map(|f| f.to_string_lossy().trim_end_matches(".profile.ron").to_string())
+        .unwrap_or_else(|| "unnamed".into())
+}
+
+pub fn load_profile_library(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(ProfileLibrary {
+        folder: asset_server.load_folder(PROFILES_FOLDER),
+    });
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProfileShapeFile {
+    #[serde(default)]
+    vertices: Vec<VertexFile>,
+    #[serde(default)]
+    line_indices: Vec<usize>,
+    //used instead of vertices and line_indices when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    outline: Option<OutlineFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OutlineFile {
+    points: Vec<(f32, f32)>,
+    #[serde(default)]
+    closed: bool,
+    //indices of points with sharp edges
+    #[serde(default)]
+    hard: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct VertexFile {
+    point: (f32, f32),
+    normal: (f32, f32),
+    u: f32,
+}
+
+#[derive(Debug, Error)]
+pub enum ProfileShapeError {
+    #[error("could not read profile: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse profile: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("could not write profile: {0}")]
+    Write(#[from] ron::Error),
+    #[error("profile file {0:?} exists already")]
+    Exists(PathBuf),
+    #[error("profile has no vertices")]
+    NoVertices,
+    #[error("line indices should go in pairs, got {0} indices")]
+    OddLineIndices(usize),
+    #[error("line index {index} is out of range, profile has {vertex_count} vertices")]
+    IndexOutOfRange { index: usize, vertex_count: usize },
+    #[error("vertex {0} has zero normal")]
+    ZeroNormal(usize),
+    #[error("hard point {index} is out of range, outline has {point_count} points")]
+    HardPointOutOfRange { index: usize, point_count: usize },
+}
+
+#[derive(Default)]
+pub struct ProfileShapeLoader;
+
+impl AssetLoader for ProfileShapeLoader {
+    type Asset = ProfileShape;
+    type Settings = ();
+    type Error = ProfileShapeError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        ProfileShape::parse(&bytes)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["profile.ron"]
+    }
+}
+
+impl ProfileShape {
+    pub fn parse(bytes: &[u8]) -> Result<Self, ProfileShapeError> {
+        let file: ProfileShapeFile = ron::de::from_bytes(bytes)?;
+
+        let shape = match file.outline {
+            Some(outline) => {
+                let points: Vec<Vec2> = outline.points.into_iter().map(Vec2::from).collect();
+                if let Some(&index) = outline.hard.iter().find(|&&i| i >= points.len()) {
+                    return Err(ProfileShapeError::HardPointOutOfRange { index, point_count: points.len() });
+                }
+                let mut hard = vec![false; points.len()];
+                for i in outline.hard {
+                    hard[i] = true;
+                }
+
+                ProfileShape::from_polyline(&points, outline.closed, &hard)
+            }
+            None => ProfileShape {
+                vertices: file.vertices
+                    .into_iter()
+                    .map(|v| Vertex {
+                        point: Vec2::from(v.point),
+                        normal: Vec2::from(v.normal).normalize_or_zero(),
+                        u: v.u,
+                    })
+                    .collect(),
+                line_indices: file.line_indices,
+            },
+        };
+        shape.validate()?;
+
+        Ok(shape)
+    }
+
+    //vertices and line indices as they are, the loader reads them back to the same shape
+    pub fn to_ron(&self) -> Result<String, ProfileShapeError> {
+        self.validate()?;
+        let file = ProfileShapeFile {
+            vertices: self.vertices
+                .iter()
+                .map(|v| VertexFile { point: v.point.into(), normal: v.normal.into(), u: v.u })
+                .collect(),
+            line_indices: self.line_indices.clone(),
+            outline: None,
+        };
+
+        Ok(ron::ser::to_string_pretty(&file, PrettyConfig::default())?)
+    }
+
+    pub fn validate(&self) -> Result<(), ProfileShapeError> {
+        if self.vertices.is_empty() {
+            return Err(ProfileShapeError::NoVertices);
+        }
+        if self.line_count() % 2 != 0 {
+            return Err(ProfileShapeError::OddLineIndices(self.line_count()));
+        }
+        if let Some(&index) = self.line_indices.iter().find(|&&i| i >= self.vertex_count()) {
+            return Err(ProfileShapeError::IndexOutOfRange { index, vertex_count: self.vertex_count() });
+        }
+        if let Some(i) = self.vertices.iter().position(|v| v.normal == Vec2::ZERO) {
+            return Err(ProfileShapeError::ZeroNormal(i));
+        }
+
+        Ok(())
+    }
+}
+
+//writes a preset as a profile file into assets/profiles, for artists to start a design from.
+//the file is named after the preset, an existing one is not overwritten
+pub struct SaveProfile {
+    pub profile: ProfileKind,
+}
+
+impl Command for SaveProfile {
+    fn apply(self, world: &mut World) {
+        let Some(shape) = self.profile.shape(world.resource::<Assets<ProfileShape>>()) else { return; };
+        let stem = self.profile.name().to_lowercase().replace([' ', '-'], "_");
+        let path = Path::new(ASSETS_DIR).join(PROFILES_FOLDER).join(format!("{stem}.profile.ron"));
+
+        let result = shape.to_ron().and_then(|text| {
+            if path.exists() {
+                return Err(ProfileShapeError::Exists(path.clone()));
+            }
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            Ok(fs::write(&path, text)?)
+        });
+
+        match result {
+            Ok(()) => info!("saved profile to {:?}", path),
+            Err(e) => error!("saving profile to {:?} failed: {e}", path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(point: Vec2, normal: Vec2) -> Vertex {
+        Vertex { point, normal, u: 0. }
+    }
+
+    fn segment() -> ProfileShape {
+        ProfileShape {
+            vertices: vec![vertex(Vec2::ZERO, Vec2::Y), vertex(Vec2::X, Vec2::Y)],
+            line_indices: vec![0, 1],
+        }
+    }
+
+    #[test]
+    fn valid_shape_passes() {
+        assert!(segment().validate().is_ok());
+    }
+
+    #[test]
+    fn no_vertices() {
+        let shape = ProfileShape { vertices: vec![], line_indices: vec![] };
+        assert!(matches!(shape.validate(), Err(ProfileShapeError::NoVertices)));
+    }
+
+    #[test]
+    fn odd_line_indices() {
+        let shape = ProfileShape { line_indices: vec![0, 1, 1], ..segment() };
+        assert!(matches!(shape.validate(), Err(ProfileShapeError::OddLineIndices(3))));
+    }
+
+    #[test]
+    fn index_out_of_range() {
+        let shape = ProfileShape { line_indices: vec![0, 2], ..segment() };
+        assert!(matches!(
+            shape.validate(),
+            Err(ProfileShapeError::IndexOutOfRange { index: 2, vertex_count: 2 })
+        ));
+    }
+
+    #[test]
+    fn zero_normal() {
+        let mut shape = segment();
+        shape.vertices[1].normal = Vec2::ZERO;
+        assert!(matches!(shape.validate(), Err(ProfileShapeError::ZeroNormal(1))));
+    }
+
+    #[test]
+    fn hard_point_out_of_range() {
+        let text = "(outline: Some((points: [(0.0, 0.0), (1.0, 0.0)], hard: [2])))";
+        assert!(matches!(
+            ProfileShape::parse(text.as_bytes()),
+            Err(ProfileShapeError::HardPointOutOfRange { index: 2, point_count: 2 })
+        ));
+    }
+
+    #[test]
+    fn saved_preset_loads_back() {
+        let shape = ProfileShape::half_pipe(6, 120.);
+        let loaded = ProfileShape::parse(shape.to_ron().unwrap().as_bytes()).unwrap();
+        assert_eq!(loaded.line_indices, shape.line_indices);
+        for (a, b) in loaded.vertices.iter().zip(shape.vertices.iter()) {
+            assert!(a.point.distance(b.point) < 1e-6);
+            assert!(a.normal.distance(b.normal) < 1e-6);
+            assert!((a.u - b.u).abs() < 1e-6);
+        }
+    }
+}
@@ -1,6 +1,11 @@
-use std::vec;
-use bevy::math::*;
+use std::{f32::consts::{FRAC_PI_2, PI, TAU}, vec};
+use bevy::{asset::{Asset, Assets, Handle}, math::*, reflect::Reflect};
+use super::profile_asset::profile_name;
 
+//squared distance under which two vertices are the same point of the outline
+const SAME_POINT_EPS: f32 = 1e-8;
+
+#[derive(Clone, Copy, Reflect)]
 pub struct Vertex {
 	pub point: Vec2,
 	pub normal: Vec2,
@@ -9,6 +14,7 @@ pub struct Vertex {
 }
 
 //a shape that is translated across curve after which every step profile would be created
+#[derive(Asset, Reflect, Clone)]
 pub struct ProfileShape {
 	pub vertices: Vec<Vertex>,
 	pub line_indices: Vec<usize>,
@@ -36,33 +42,341 @@ impl ProfileShape {
 		dist
 	}
 
-	pub fn circle_8 () -> Self {
-		let sqrt = 1./f32::sqrt(2.);
-		// let sin45_half = f32::sin(f32::to_radians(45.)/2.);
-		// let cos45_half = f32::cos(f32::to_radians(45.)/2.);
+	//samples the outline at given u's, u's should be ascending.
+	//every pair of neighbour u's becomes a line with its own two vertices inside one line of this shape,
+	//so hard corners stay hard and shapes resampled with the same u's have the same topology
+	pub fn resample(&self, us: &[f32]) -> Self {
+		let lines: Vec<(Vertex, Vertex)> = self.line_indices
+			.chunks(2)
+			.map(|l| (self.vertices[l[0]], self.vertices[l[1]]))
+			.collect();
+
+		//line the u is on, the closest one if u falls in a gap between lines
+		let find_line = |u: f32| {
+			let dist = |(a, b): &&(Vertex, Vertex)| {
+				let (lo, hi) = (a.u.min(b.u), a.u.max(b.u));
+				if u < lo { lo - u } else if u > hi { u - hi } else { 0. }
+			};
+			lines.iter().min_by(|l, r| dist(l).total_cmp(&dist(r)))
+		};
+		let sample = |(a, b): &(Vertex, Vertex), u: f32| {
+			let k = if (b.u - a.u).abs() > f32::EPSILON { ((u - a.u) / (b.u - a.u)).clamp(0., 1.) } else { 0. };
+			Vertex {
+				point: a.point.lerp(b.point, k),
+				normal: a.normal.lerp(b.normal, k).normalize_or_zero(),
+				u,
+			}
+		};
+
+		let mut vertices = Vec::<Vertex>::new();
+		let mut line_indices = Vec::<usize>::new();
+		for pair in us.windows(2) {
+			let Some(line) = find_line((pair[0] + pair[1]) / 2.) else { continue; };
+			vertices.push(sample(line, pair[0]));
+			vertices.push(sample(line, pair[1]));
+			line_indices.push(vertices.len() - 2);
+			line_indices.push(vertices.len() - 1);
+		}
+
+		Self { vertices, line_indices }
+	}
+
+	//resamples both shapes with the same u's so they can be blended with lerp
+	pub fn make_compatible(a: &Self, b: &Self) -> (Self, Self) {
+		//corners of both shapes and some even steps so curved parts of one shape follow the other
+		const EVEN_STEPS: usize = 16;
+		let mut us: Vec<f32> = a.vertices.iter()
+			.chain(b.vertices.iter())
+			.map(|v| v.u)
+			.chain((0..=EVEN_STEPS).map(|i| i as f32 / EVEN_STEPS as f32))
+			.collect();
+		us.sort_by(f32::total_cmp);
+		us.dedup_by(|x, y| (*x - *y).abs() < 1e-4);
+
+		(a.resample(&us), b.resample(&us))
+	}
+
+	//shapes should have the same topology, see make_compatible
+	pub fn lerp(&self, other: &Self, k: f32) -> Self {
 		Self {
-			vertices: vec![
-				//TODO: set correct normals
-				Vertex{ point: Vec2::new(0., 1.),       normal:  Vec2::Y, u: 1.000},
-				Vertex{ point: Vec2::new(0., 1.),       normal:  Vec2::Y, u: 0.000},
-				Vertex{ point: Vec2::new(-sqrt, sqrt),  normal: -Vec2::X, u: 0.125},
-				Vertex{ point: Vec2::new(-sqrt, sqrt),  normal: -Vec2::X, u: 0.125},
-				Vertex{ point: Vec2::new(-1., 0.),      normal: -Vec2::X, u: 0.125 * 2.},
-				Vertex{ point: Vec2::new(-1., 0.),      normal: -Vec2::X, u: 0.125 * 2.},
-				Vertex{ point: Vec2::new(-sqrt, -sqrt), normal: -Vec2::Y, u: 0.125 * 3.},
-				Vertex{ point: Vec2::new(-sqrt, -sqrt), normal: -Vec2::Y, u: 0.125 * 3.},
-				Vertex{ point: Vec2::new(0., -1.),      normal: -Vec2::Y, u: 0.125 * 4.},
-				Vertex{ point: Vec2::new(0., -1.),      normal: -Vec2::Y, u: 0.125 * 4.},
-				Vertex{ point: Vec2::new(sqrt, -sqrt),  normal:  Vec2::X, u: 0.125 * 5.},
-				Vertex{ point: Vec2::new(sqrt, -sqrt),  normal:  Vec2::X, u: 0.125 * 5.},
-				Vertex{ point: Vec2::new(1., 0.),       normal:  Vec2::X, u: 0.125 * 6.},
-				Vertex{ point: Vec2::new(1., 0.),       normal:  Vec2::X, u: 0.125 * 6.},
-				Vertex{ point: Vec2::new(sqrt, sqrt),   normal:  Vec2::Y, u: 0.125 * 7.},
-				Vertex{ point: Vec2::new(sqrt, sqrt),   normal:  Vec2::Y, u: 0.125 * 7.},
-			],
-			line_indices: vec![
-				1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 0
-			]
+			vertices: self.vertices
+				.iter()
+				.zip(other.vertices.iter())
+				.map(|(a, b)| Vertex {
+					point: a.point.lerp(b.point, k),
+					normal: a.normal.lerp(b.normal, k).try_normalize().unwrap_or(a.normal),
+					u: a.u + (b.u - a.u) * k,
+				})
+				.collect(),
+			line_indices: self.line_indices.clone(),
+		}
+	}
+
+	//normals do not change with uniform scale
+	pub fn scaled(mut self, scale: f32) -> Self {
+		for v in self.vertices.iter_mut() {
+			v.point *= scale;
+		}
+		self
+	}
+
+	//outline pushed along normals by distance, same topology.
+	//vertices on the same point move together so hard corners stay closed and keep the wall even
+	pub fn offset(&self, distance: f32) -> Self {
+		let vertices = self.vertices
+			.iter()
+			.map(|v| {
+				let dir = self.vertices
+					.iter()
+					.filter(|o| o.point.distance_squared(v.point) < SAME_POINT_EPS)
+					.map(|o| o.normal)
+					.sum::<Vec2>()
+					.try_normalize()
+					.unwrap_or(v.normal);
+				//miter, but not too long on sharp corners
+				let len = distance / dir.dot(v.normal).max(0.25);
+				Vertex { point: v.point + dir * len, ..*v }
+			})
+			.collect();
+
+		Self { vertices, line_indices: self.line_indices.clone() }
+	}
+
+	//vertices where an open outline starts and ends, empty for closed ones
+	pub fn open_ends(&self) -> (Vec<usize>, Vec<usize>) {
+		let lines: Vec<(usize, usize)> = self.line_indices.chunks(2).map(|l| (l[0], l[1])).collect();
+		let same = |a: usize, b: usize| self.vertices[a].point.distance_squared(self.vertices[b].point) < SAME_POINT_EPS;
+
+		let starts = lines.iter()
+			.map(|&(a, _)| a)
+			.filter(|&a| !lines.iter().any(|&(_, b)| same(a, b)))
+			.collect();
+		let ends = lines.iter()
+			.map(|&(_, b)| b)
+			.filter(|&b| !lines.iter().any(|&(a, _)| same(a, b)))
+			.collect();
+
+		(starts, ends)
+	}
+
+	//outline of a profile, points should go counter-clockwise so normals look outwards.
+	//hard points get two vertices with normals of their edges, smooth ones one vertex with averaged normal.
+	//u goes from 0 to 1 along the outline by distance
+	pub fn from_polyline(points: &[Vec2], closed: bool, hard: &[bool]) -> Self {
+		let n = points.len();
+		let edge_count = if closed { n } else { n.saturating_sub(1) };
+		let is_hard = |p: usize| hard.get(p).copied().unwrap_or(false);
+		//right side of the edge is outside for counter-clockwise outline
+		let edge_normal = |e: usize| {
+			let d = points[(e + 1) % n] - points[e];
+			Vec2::new(d.y, -d.x).normalize_or_zero()
+		};
+		let smooth_normal = |e_in: usize, e_out: usize| {
+			(edge_normal(e_in) + edge_normal(e_out)).try_normalize().unwrap_or(edge_normal(e_out))
+		};
+
+		let mut dists = vec![0.; edge_count + 1];
+		for e in 0..edge_count {
+			dists[e + 1] = dists[e] + (points[(e + 1) % n] - points[e]).length();
+		}
+		let perimeter = dists[edge_count].max(f32::EPSILON);
+
+		let mut vertices = Vec::<Vertex>::new();
+		let mut line_indices = Vec::<usize>::new();
+		let mut prev_end: Option<usize> = None;
+
+		for e in 0..edge_count {
+			let (i, j) = (e, (e + 1) % n);
+
+			let start = match prev_end {
+				Some(v) if !is_hard(i) => v,
+				_ => {
+					//start of a closed outline is a seam: split for u, but still smooth if not hard
+					let normal = if e == 0 && closed && !is_hard(i) { smooth_normal(edge_count - 1, 0) } else { edge_normal(e) };
+					vertices.push(Vertex { point: points[i], normal, u: dists[e] / perimeter });
+					vertices.len() - 1
+				}
+			};
+
+			let has_next = closed || e + 1 < edge_count;
+			let normal = if has_next && !is_hard(j) { smooth_normal(e, (e + 1) % edge_count) } else { edge_normal(e) };
+			vertices.push(Vertex { point: points[j], normal, u: dists[e + 1] / perimeter });
+			let end = vertices.len() - 1;
+
+			line_indices.push(start);
+			line_indices.push(end);
+			prev_end = Some(end);
+		}
+
+		Self { vertices, line_indices }
+	}
+
+	//closed round tube
+	pub fn circle(sides: usize) -> Self {
+		let sides = sides.max(3);
+		let points: Vec<Vec2> = (0..sides)
+			.map(|i| Vec2::from_angle(FRAC_PI_2 + TAU * i as f32 / sides as f32))
+			.collect();
+
+		Self::from_polyline(&points, true, &[])
+	}
+
+	//open round channel, opening is the angle of the gap on top in degrees. 180 is a half of a tube
+	pub fn half_pipe(sides: usize, opening: f32) -> Self {
+		let sides = sides.max(2);
+		let opening = opening.clamp(1., 359.).to_radians();
+		let from = FRAC_PI_2 + opening / 2.;
+		let span = TAU - opening;
+		let points: Vec<Vec2> = (0..=sides)
+			.map(|i| Vec2::from_angle(from + span * i as f32 / sides as f32))
+			.collect();
+
+		Self::from_polyline(&points, false, &[])
+	}
+
+	//open rectangular channel with sharp bottom corners
+	pub fn flume(width: f32, height: f32) -> Self {
+		let (w, h) = (width / 2., height / 2.);
+		let points = [
+			Vec2::new(-w,  h),
+			Vec2::new(-w, -h),
+			Vec2::new( w, -h),
+			Vec2::new( w,  h),
+		];
+
+		Self::from_polyline(&points, false, &[false, true, true, false])
+	}
+
+	//wide shallow channel with a flat bottom, rounded bottom corners and walls leaning out
+	pub fn raft_channel(width: f32, depth: f32) -> Self {
+		const CORNER_STEPS: usize = 4;
+		let (w, d) = (width / 2., depth / 2.);
+		let r = (depth * 0.4).min(w);
+		let lean = depth * 0.25;
+
+		let mut points = vec![Vec2::new(-w - lean, d)];
+		//left corner from the wall down to the bottom, then right one from the bottom up to the wall
+		for (center, from) in [(Vec2::new(-w + r, -d + r), PI), (Vec2::new(w - r, -d + r), 3. * FRAC_PI_2)] {
+			for i in 0..=CORNER_STEPS {
+				points.push(center + r * Vec2::from_angle(from + FRAC_PI_2 * i as f32 / CORNER_STEPS as f32));
+			}
+		}
+		points.push(Vec2::new(w + lean, d));
+
+		//walls meet the rounded corners with a kink where they lean out
+		let mut hard = vec![false; points.len()];
+		hard[1] = true;
+		hard[points.len() - 2] = true;
+
+		Self::from_polyline(&points, false, &hard)
+	}
+}
+
+//profile a segment is extruded with
+#[derive(Debug, Clone, PartialEq, Reflect)]
+pub enum ProfileKind {
+	Circle { sides: usize },
+	//opening in degrees
+	HalfPipe { sides: usize, opening: f32 },
+	Flume { width: f32, height: f32 },
+	RaftChannel { width: f32, depth: f32 },
+	//loaded from assets/profiles
+	Asset(Handle<ProfileShape>),
+}
+
+impl Default for ProfileKind {
+	fn default() -> Self {
+		ProfileKind::Circle { sides: 8 }
+	}
+}
+
+impl ProfileKind {
+	pub const PRESETS: [ProfileKind; 4] = [
+		ProfileKind::Circle { sides: 8 },
+		ProfileKind::HalfPipe { sides: 8, opening: 120. },
+		ProfileKind::Flume { width: 2., height: 1.5 },
+		ProfileKind::RaftChannel { width: 4., depth: 1.5 },
+	];
+
+	pub fn name(&self) -> String {
+		match self {
+			ProfileKind::Circle { .. } => "Circle".into(),
+			ProfileKind::HalfPipe { .. } => "Half-pipe".into(),
+			ProfileKind::Flume { .. } => "Flume".into(),
+			ProfileKind::RaftChannel { .. } => "Raft channel".into(),
+			ProfileKind::Asset(handle) => profile_name(handle),
 		}
 	}
-}
\ No newline at end of file
+
+	//None while the profile asset is loading or failed to load
+	pub fn shape(&self, profiles: &Assets<ProfileShape>) -> Option<ProfileShape> {
+		match self {
+			ProfileKind::Circle { sides } => Some(ProfileShape::circle(*sides)),
+			ProfileKind::HalfPipe { sides, opening } => Some(ProfileShape::half_pipe(*sides, *opening)),
+			ProfileKind::Flume { width, height } => Some(ProfileShape::flume(*width, *height)),
+			ProfileKind::RaftChannel { width, depth } => Some(ProfileShape::raft_channel(*width, *depth)),
+			ProfileKind::Asset(handle) => profiles.get(handle).cloned(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::tube_segment::{oriented_point::OrientedPoint, tube_mesh::TubeMeshBuffers};
+
+	const EPS: f32 = 1e-4;
+
+	fn shapes() -> Vec<(String, ProfileShape)> {
+		let square = [Vec2::new(-1., -1.), Vec2::new(1., -1.), Vec2::new(1., 1.), Vec2::new(-1., 1.)];
+		ProfileKind::PRESETS
+			.iter()
+			.map(|kind| (kind.name(), kind.shape(&Assets::default()).unwrap()))
+			.chain([
+				("Square".into(), ProfileShape::from_polyline(&square, true, &[true; 4])),
+				("Narrow half-pipe".into(), ProfileShape::half_pipe(3, 300.)),
+			])
+			.collect()
+	}
+
+	#[test]
+	fn normals_are_unit_and_look_out() {
+		for (name, shape) in shapes() {
+			let centroid = shape.vertices.iter().map(|v| v.point).sum::<Vec2>() / shape.vertex_count() as f32;
+			for v in shape.vertices.iter() {
+				assert!((v.normal.length() - 1.).abs() < EPS, "{name}: normal {:?}", v.normal);
+				assert!(v.normal.dot(v.point - centroid) > 0., "{name}: normal {:?} at {:?}", v.normal, v.point);
+			}
+		}
+	}
+
+	#[test]
+	fn u_goes_from_0_to_1_along_the_outline() {
+		for (name, shape) in shapes() {
+			let us: Vec<f32> = shape.line_indices.iter().map(|i| shape.vertices[*i].u).collect();
+			assert!(us.first().unwrap().abs() < EPS, "{name}");
+			assert!((us.last().unwrap() - 1.).abs() < EPS, "{name}");
+			assert!(us.windows(2).all(|w| w[1] >= w[0]), "{name}: {us:?}");
+			//every line goes forwards
+			assert!(us.chunks(2).all(|l| l[1] > l[0]), "{name}: {us:?}");
+		}
+	}
+
+	#[test]
+	fn wall_triangles_face_along_normals() {
+		//two rings along the forward of an oriented point, like a tube wall is built
+		let rot = Quat::from_rotation_y(0.7) * Quat::from_rotation_x(0.3);
+		let ring_ops = [0., 1.].map(|z| OrientedPoint { pos: rot * Vec3::Z * z, rot });
+		for (name, shape) in shapes() {
+			let mut buffers = TubeMeshBuffers::default();
+			buffers.push_wall(&ring_ops, &[shape.clone(), shape], &[0., 1.], false);
+			for tri in buffers.tri_indices.chunks(3) {
+				let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| buffers.verts[i as usize]);
+				let face = (b - a).cross(c - a);
+				for i in tri {
+					assert!(face.dot(buffers.normals[*i as usize]) > 0., "{name}");
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,142 @@
+use bevy::{asset::LoadedFolder, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+use super::{
+    history::{EditorCommand, History, PointMove, SegmentProps},
+    profile_asset::ProfileLibrary,
+    segment_colors_ui, segment_props_ui,
+    selection::Selection,
+    slide::Slide,
+    ControlPointDraggable, RoadSegment,
+};
+
+//exact values of the selected control point and segment. edits are recorded like mouse edits,
+//curves and meshes rebuild from the changed transforms and segments the same way
+pub fn properties_ui(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    selection: Res<Selection>,
+    mut history: ResMut<History>,
+    slides: Query<&Slide>,
+    mut road_segments: Query<(Entity, &mut RoadSegment)>,
+    mut control_pts: Query<(&mut Transform, &mut ControlPointDraggable)>,
+    profile_library: Res<ProfileLibrary>,
+    folders: Res<Assets<LoadedFolder>>,
+    material_handles: Query<&Handle<StandardMaterial>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let point = selection.primary_point().filter(|p| control_pts.contains(*p));
+    //segment of the point when no segment is selected
+    let segment = selection
+        .primary_segment()
+        .or_else(|| point.and_then(|p| road_segments.iter().find(|(_, rs)| rs.pts_ids.contains(&p)).map(|(id, _)| id)))
+        .filter(|id| road_segments.contains(*id));
+    if point.is_none() && segment.is_none() { return; }
+
+    let profile_assets = profile_library.profiles(&folders);
+
+    egui::Window::new("Properties").show(contexts.ctx_mut(), |ui| {
+        if let Some(point) = point {
+            ui.strong("Control point");
+            point_ui(ui, point, &mut history, &mut road_segments, &mut control_pts);
+        }
+
+        let Some(segment) = segment else { return; };
+        ui.separator();
+        ui.strong("Segment");
+        let Ok((_, mut rs)) = road_segments.get_mut(segment) else { return; };
+        let before = SegmentProps::of(&rs);
+        let mut props = before.clone();
+        let is_first = slides.get(rs.slide).is_ok_and(|s| s.segments.first() == Some(&segment));
+        segment_props_ui(ui, &mut commands, ("properties", segment), &mut props, is_first, &profile_assets);
+        segment_colors_ui(ui, [segment, rs.inner_wall], &material_handles, &mut materials, &mut history);
+
+        if props != before {
+            props.apply_to(&mut rs);
+            history.record(EditorCommand::SetSegment { segment, before, after: props });
+        }
+    });
+}
+
+//position, tangent lengths and bank
+fn point_ui(
+    ui: &mut egui::Ui,
+    point: Entity,
+    history: &mut History,
+    road_segments: &mut Query<(Entity, &mut RoadSegment)>,
+    control_pts: &mut Query<(&mut Transform, &mut ControlPointDraggable)>,
+) {
+    let is_end_point = road_segments.iter().any(|(_, rs)| rs.is_end_point(point));
+    //bezier handles are placed from the tangent of their end point, only the length can be set
+    let follows_tangent = road_segments
+        .iter()
+        .any(|(_, rs)| !rs.curve_kind.has_free_mid_points() && rs.handle_anchor(point).is_some());
+
+    //segment, side and end point of every bezier tangent the point is on
+    let mut tangents = vec![];
+    for (id, rs) in road_segments.iter() {
+        if rs.curve_kind.has_free_mid_points() { continue; }
+        for (side, anchor, handle) in [(0, rs.pts_ids[0], rs.pts_ids[1]), (1, rs.pts_ids[3], rs.pts_ids[2])] {
+            if point == anchor || point == handle {
+                tangents.push((id, side, anchor));
+            }
+        }
+    }
+
+    let Ok((mut trm, mut cp)) = control_pts.get_mut(point) else { return; };
+    let before = *trm;
+    let mut pos = trm.translation;
+    ui.horizontal(|ui| {
+        ui.label("Position:");
+        ui.add_enabled_ui(!follows_tangent, |ui| {
+            for (value, axis) in [(&mut pos.x, "x: "), (&mut pos.y, "y: "), (&mut pos.z, "z: ")] {
+                ui.add(egui::DragValue::new(value).speed(0.05).prefix(axis).suffix(" m"));
+            }
+        });
+    });
+    if pos != trm.translation {
+        trm.translation = pos;
+        history.record(EditorCommand::MovePoints(vec![PointMove { point, before, after: *trm }]));
+    }
+
+    if is_end_point {
+        let mut bank = cp.bank.to_degrees();
+        ui.horizontal(|ui| {
+            ui.label("Bank:");
+            ui.add(egui::DragValue::new(&mut bank).speed(1.).range(-90.0..=90.).suffix("°"));
+        });
+        if bank != cp.bank.to_degrees() {
+            let before = cp.bank;
+            cp.bank = bank.to_radians();
+            history.record(EditorCommand::SetBank { point, before, after: cp.bank });
+        }
+    }
+
+    if tangents.is_empty() { return; }
+    ui.horizontal(|ui| {
+        ui.label("Tangent length:");
+        for (segment, side, anchor) in tangents {
+            let Ok((_, anchor_cp)) = control_pts.get(anchor) else { continue; };
+            let shared_len = anchor_cp.tangent_len;
+            let Ok((_, rs)) = road_segments.get(segment) else { continue; };
+            let own = rs.handle_lens[side].is_some();
+            let len = rs.handle_len(side, shared_len);
+
+            //into the end point from the segment before it, out of it into the segment after it
+            let mut new_len = len;
+            let prefix = if side == 0 { "out: " } else { "in: " };
+            ui.add(egui::DragValue::new(&mut new_len).speed(0.05).range(0.01..=100.).prefix(prefix).suffix(" m"));
+            if new_len == len { continue; }
+
+            //same as dragging the handle: own length of the segment, or the one the joint shares
+            if own {
+                let Ok((_, mut rs)) = road_segments.get_mut(segment) else { continue; };
+                let before = SegmentProps::of(&rs);
+                rs.handle_lens[side] = Some(new_len);
+                history.record(EditorCommand::SetSegment { segment, before, after: SegmentProps::of(&rs) });
+            } else if let Ok((_, mut anchor_cp)) = control_pts.get_mut(anchor) {
+                anchor_cp.tangent_len = new_len;
+                history.record(EditorCommand::SetTangentLen { point: anchor, before: shared_len, after: new_len });
+            }
+        }
+    });
+}
@@ -0,0 +1,236 @@
+use bevy::{
+    color::palettes::css::{ORANGE, YELLOW},
+    prelude::*,
+};
+use bevy_egui::{egui, EguiContexts};
+use bevy_mod_raycast::prelude::*;
+use super::{curve_query::SlideHover, slide::Slide, slide_edit::SplitSegment, tangent_gizmo::TangentGizmo, ControlPointDraggable, ControlPointState, CustomMesh, RoadSegment};
+
+//smaller boxes are clicks
+const MIN_BOX_SIZE: f32 = 4.;
+
+//what the editor works on. selecting a segment selects its control points too,
+//so moving a selection always moves points
+#[derive(Resource, Default)]
+pub struct Selection {
+    pub points: Vec<Entity>,
+    pub segments: Vec<Entity>,
+    //control point or segment under the cursor
+    pub hovered: Option<Entity>,
+    //point the drag started on, other selected points keep their offsets from it
+    pub lead: Option<Entity>,
+    //cursor position box select started at
+    box_start: Option<Vec2>,
+}
+
+impl Selection {
+    pub fn is_selected(&self, entity: Entity) -> bool {
+        self.points.contains(&entity) || self.segments.contains(&entity)
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.segments.clear();
+        self.lead = None;
+    }
+
+    pub fn add_point(&mut self, point: Entity) {
+        if !self.points.contains(&point) {
+            self.points.push(point);
+        }
+    }
+
+    pub fn toggle_point(&mut self, point: Entity) {
+        if let Some(i) = self.points.iter().position(|p| *p == point) {
+            self.points.remove(i);
+        } else {
+            self.points.push(point);
+        }
+    }
+
+    pub fn add_segment(&mut self, segment: Entity, rs: &RoadSegment) {
+        if !self.segments.contains(&segment) {
+            self.segments.push(segment);
+        }
+        for pt_id in rs.pts_ids {
+            self.add_point(pt_id);
+        }
+    }
+
+    //last selected point
+    pub fn primary_point(&self) -> Option<Entity> {
+        self.points.last().copied()
+    }
+
+    //last selected segment
+    pub fn primary_segment(&self) -> Option<Entity> {
+        self.segments.last().copied()
+    }
+}
+
+//click selects a point or a segment, shift adds to the selection, alt takes the whole slide of a segment.
+//pressing on a selected point drags the whole selection until the button is released, wherever the cursor goes.
+//pressing on nothing starts a box select. ctrl+click on a segment inserts a point there
+pub fn update_selection(
+    mut commands: Commands,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    windows: Query<&Window>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut contexts: EguiContexts,
+    mut raycast: Raycast,
+    mut selection: ResMut<Selection>,
+    tangent_gizmo: Res<TangentGizmo>,
+    slide_hover: Res<SlideHover>,
+    mut control_points: Query<(Entity, &GlobalTransform, &mut ControlPointDraggable)>,
+    road_segments: Query<&RoadSegment, With<CustomMesh>>,
+    slides: Query<&Slide>,
+) {
+    let (camera, camera_transform) = cameras.single();
+    let Some(cursor_position) = windows.single().cursor_position() else {return; };
+    let Some(ray) = camera.viewport_to_world(camera_transform, cursor_position) else {return;};
+
+    let ctx = contexts.ctx_mut();
+    //tangent rings take the click
+    let over_ui = ctx.is_pointer_over_area() || ctx.wants_pointer_input() || tangent_gizmo.hovered.is_some();
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let alt = keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+
+    //points first, they are inside the tube
+    let hit_point = raycast
+        .cast_ray(ray, &RaycastSettings { filter: &|e| control_points.contains(e), ..default() })
+        .first()
+        .map(|(e, _)| *e);
+    let hovered = hit_point.or_else(|| raycast
+        .cast_ray(ray, &RaycastSettings { filter: &|e| road_segments.contains(e), ..default() })
+        .first()
+        .map(|(e, _)| *e)
+    );
+    let hovered = if over_ui { None } else { hovered };
+    if selection.hovered != hovered {
+        selection.hovered = hovered;
+    }
+
+    if buttons.just_pressed(MouseButton::Left) && !over_ui {
+        if let Some(point) = hit_point {
+            if shift {
+                selection.toggle_point(point);
+            } else if !selection.points.contains(&point) {
+                selection.clear();
+                selection.add_point(point);
+            }
+
+            if selection.points.contains(&point) {
+                selection.lead = Some(point);
+                for pt_id in selection.points.iter() {
+                    if let Ok((_, _, mut cp)) = control_points.get_mut(*pt_id) {
+                        cp.state = ControlPointState::Drag;
+                    }
+                }
+            }
+        } else if let (true, Some(hit)) = (ctrl, slide_hover.hit) {
+            commands.add(SplitSegment { segment: hit.segment, t: hit.t });
+        } else if let Some(segment) = hovered {
+            if !shift {
+                selection.clear();
+            }
+            let Ok(rs) = road_segments.get(segment) else {return;};
+            let segments = if alt {
+                slides.get(rs.slide).map(|s| s.segments.clone()).unwrap_or_default()
+            } else {
+                vec![segment]
+            };
+            for id in segments {
+                if let Ok(rs) = road_segments.get(id) {
+                    selection.add_segment(id, rs);
+                }
+            }
+        } else {
+            selection.box_start = Some(cursor_position);
+        }
+    }
+
+    if buttons.just_released(MouseButton::Left) {
+        selection.lead = None;
+        for (_, _, mut cp) in control_points.iter_mut() {
+            if cp.state != ControlPointState::None {
+                cp.state = ControlPointState::None;
+            }
+        }
+
+        if let Some(start) = selection.box_start.take() {
+            let rect = Rect::from_corners(start, cursor_position);
+            if !shift {
+                selection.clear();
+            }
+            if rect.width() >= MIN_BOX_SIZE || rect.height() >= MIN_BOX_SIZE {
+                for (id, trm, _) in control_points.iter() {
+                    let inside = camera
+                        .world_to_viewport(camera_transform, trm.translation())
+                        .is_some_and(|p| rect.contains(p));
+                    if inside {
+                        selection.add_point(id);
+                    }
+                }
+            }
+        }
+    }
+
+    //box follows the cursor
+    if let Some(start) = selection.box_start {
+        let rect = egui::Rect::from_two_pos(
+            egui::pos2(start.x, start.y),
+            egui::pos2(cursor_position.x, cursor_position.y),
+        );
+        let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("box_select")));
+        painter.rect_filled(rect, 0., egui::Color32::from_rgba_unmultiplied(255, 165, 0, 20));
+        painter.rect_stroke(rect, 0., egui::Stroke::new(1., egui::Color32::from_rgb(255, 165, 0)));
+    }
+}
+
+//points are tinted, selected segments glow
+pub fn tint_selection(
+    selection: Res<Selection>,
+    points: Query<(Entity, &Handle<StandardMaterial>), With<ControlPointDraggable>>,
+    segments: Query<(Entity, &Handle<StandardMaterial>), With<RoadSegment>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !selection.is_changed() { return; }
+
+    for (id, handle) in points.iter() {
+        let color = if selection.points.contains(&id) {
+            ORANGE.with_alpha(0.8).into()
+        } else if selection.hovered == Some(id) {
+            YELLOW.with_alpha(0.5).into()
+        } else {
+            Color::srgba(1., 1., 1., 0.2)
+        };
+        //get_mut marks the material changed
+        if materials.get(handle).is_some_and(|m| m.base_color != color) {
+            if let Some(m) = materials.get_mut(handle) { m.base_color = color; }
+        }
+    }
+
+    for (id, handle) in segments.iter() {
+        let emissive = if selection.segments.contains(&id) {
+            LinearRgba::rgb(0.4, 0.2, 0.)
+        } else {
+            LinearRgba::BLACK
+        };
+        if materials.get(handle).is_some_and(|m| m.emissive != emissive) {
+            if let Some(m) = materials.get_mut(handle) { m.emissive = emissive; }
+        }
+    }
+}
+
+//curve color of a segment
+pub fn curve_color(selection: &Selection, segment: Entity) -> Color {
+    if selection.segments.contains(&segment) {
+        ORANGE.into()
+    } else if selection.hovered == Some(segment) {
+        YELLOW.into()
+    } else {
+        Color::WHITE
+    }
+}
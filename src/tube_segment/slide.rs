@@ -0,0 +1,336 @@
+use bevy::{
+    color::palettes::css::AQUA,
+    ecs::world::Command,
+    prelude::*,
+    render::{
+        mesh::PrimitiveTopology,
+        render_asset::RenderAssetUsages,
+        texture::{ImageAddressMode, ImageLoaderSettings, ImageSampler, ImageSamplerDescriptor},
+    },
+};
+use super::{curve_kind::SegmentCurve, tube_mesh::LOD_COUNT, ControlPointDraggable, CustomMesh, InnerWall, RoadSegment, SegmentLods};
+
+pub const SLIDE_TEXTURE: &str = "textures/uv_mapper.png";
+
+//a whole slide. owns its segments in order from the start of the slide to its end.
+//neighbour segments share a control point: end of segment n is the start of segment n + 1
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Slide {
+    pub segments: Vec<Entity>,
+}
+
+impl Slide {
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn last_segment(&self) -> Option<Entity> {
+        self.segments.last().copied()
+    }
+
+    pub fn length(&self, road_segments: &Query<&RoadSegment>) -> f32 {
+        self.segments
+            .iter()
+            .filter_map(|id| road_segments.get(*id).ok())
+            .map(|rs| rs.get_len())
+            .sum()
+    }
+
+    //distance from the slide start -> (segment, t inside that segment)
+    pub fn locate_distance<'a>(&self, distance: f32, road_segments: &'a Query<&RoadSegment>) -> Option<(&'a RoadSegment, f32)> {
+        let mut segments = self.segments
+            .iter()
+            .filter_map(|id| road_segments.get(*id).ok())
+            .peekable();
+
+        let mut left = distance.max(0.);
+        while let Some(rs) = segments.next() {
+            let len = rs.get_len();
+            if left <= len || segments.peek().is_none() {
+                return Some((rs, rs.arc_lengths.t_at_distance(left)));
+            }
+            left -= len;
+        }
+
+        None
+    }
+
+    //all segment curves glued together. segments without a computed curve yet are skipped
+    pub fn path(&self, road_segments: &Query<&RoadSegment>) -> SlidePath {
+        SlidePath {
+            curves: self.segments
+                .iter()
+                .filter_map(|id| road_segments.get(*id).ok())
+                .map(|rs| rs.curve.clone())
+                .collect(),
+        }
+    }
+}
+
+//slide as one continuous curve.
+//t goes from 0 to segment_count(), integer part is a segment index, fractional part is t inside that segment
+pub struct SlidePath {
+    pub curves: Vec<SegmentCurve>,
+}
+
+impl SlidePath {
+    pub fn segment_count(&self) -> usize {
+        self.curves.len()
+    }
+
+    pub fn domain(&self) -> f32 {
+        self.segment_count() as f32
+    }
+
+    //slide t -> (segment index, segment t)
+    pub fn locate(&self, t: f32) -> (usize, f32) {
+        let last = self.segment_count().saturating_sub(1);
+        let t = t.clamp(0., self.domain());
+        let idx = (t.floor() as usize).min(last);
+        (idx, t - idx as f32)
+    }
+
+    pub fn position(&self, t: f32) -> Vec3 {
+        let (idx, t) = self.locate(t);
+        self.curves[idx].position(t)
+    }
+}
+
+//spawns a slide from bezier points: p0, p1, p2, p3, p4, p5, p6, ...
+//every 3rd point is an end point shared by two neighbour segments, the ones between are tangent handles.
+//points count should be 3 * segments + 1.
+//handle after a joint (p4, p7, ...) is ignored: it is the handle before the joint mirrored over it,
+//so the slide has no kink there. a handle not lying that way is warned about
+pub struct SpawnSlide {
+    pub positions: Vec<Vec3>,
+}
+
+impl Command for SpawnSlide {
+    fn apply(self, world: &mut World) {
+        spawn_slide(world, &self.positions);
+    }
+}
+
+//None if points count is wrong
+pub fn spawn_slide(world: &mut World, pts: &[Vec3]) -> Option<Entity> {
+    if pts.len() < 4 || (pts.len() - 1) % 3 != 0 {
+        warn!("SpawnSlide: expected 3 * n + 1 points, got {}", pts.len());
+        return None;
+    }
+
+    let slide = world
+        .spawn((Name::new("Slide"), SpatialBundle::default(), Slide::default()))
+        .id();
+
+    let start = spawn_control_point(world, slide, pts[0], pts[1] - pts[0]);
+    spawn_segment(world, slide, start, pts[1], pts[2], pts[3]);
+
+    for (i, chunk) in pts[4..].chunks(3).enumerate() {
+        let joint = 3 * (i + 1);
+        let mirrored = (pts[joint] - pts[joint - 1]).normalize_or_zero();
+        if (chunk[0] - pts[joint]).normalize_or_zero().dot(mirrored) < 0.999 {
+            warn!("SpawnSlide: handle {} is not in line with handle {} over the joint, it is ignored", joint + 1, joint - 1);
+        }
+        AppendSegment {
+            slide,
+            end_handle: chunk[1],
+            end: chunk[2],
+        }
+        .apply(world);
+    }
+
+    Some(slide)
+}
+
+//adds a segment to the end of a slide. start of the new segment is the end point of the last one.
+//start tangent is not free: it continues the tangent of the last segment (G1 continuity on the joint)
+pub struct AppendSegment {
+    pub slide: Entity,
+    pub end_handle: Vec3,
+    pub end: Vec3,
+}
+
+impl Command for AppendSegment {
+    fn apply(self, world: &mut World) {
+        append_segment(world, self.slide, self.end_handle, self.end);
+    }
+}
+
+//None if the slide has no segment to continue
+pub fn append_segment(world: &mut World, slide: Entity, end_handle: Vec3, end: Vec3) -> Option<Entity> {
+    let last_end = world
+        .get::<Slide>(slide)
+        .and_then(|s| s.last_segment())
+        .and_then(|last| world.get::<RoadSegment>(last))
+        .map(|rs| rs.end_pt_id());
+
+    let Some(start) = last_end else {
+        warn!("AppendSegment: slide {:?} has no segment to continue", slide);
+        return None;
+    };
+    let start_trm = world.get::<Transform>(start).copied()?;
+    let tangent_len = world.get::<ControlPointDraggable>(start).map_or(1., |cp| cp.tangent_len);
+
+    let start_handle = start_trm.translation + start_trm.forward() * tangent_len;
+    Some(spawn_segment(world, slide, start, start_handle, end_handle, end))
+}
+
+fn spawn_segment(
+    world: &mut World,
+    slide: Entity,
+    start: Entity,
+    start_handle: Vec3,
+    end_handle: Vec3,
+    end: Vec3,
+) -> Entity {
+    let idx = world.get::<Slide>(slide).map_or(0, |s| s.segment_count());
+
+    let handle_a = spawn_control_point(world, slide, start_handle, Vec3::ZERO);
+    let handle_b = spawn_control_point(world, slide, end_handle, Vec3::ZERO);
+    let end_id = spawn_control_point(world, slide, end, end - end_handle);
+    insert_segment(world, slide, idx, [start, handle_a, handle_b, end_id])
+}
+
+//segment through control points that exist already, put into the slide at idx.
+//neighbours are not touched, their shared points have to be in pts_ids
+pub fn insert_segment(world: &mut World, slide: Entity, idx: usize, pts_ids: [Entity; 4]) -> Entity {
+
+    //empty until the first build, every level of detail has its own asset
+    let mut empty_meshes = || -> [Handle<Mesh>; LOD_COUNT] {
+        std::array::from_fn(|_| world.resource_mut::<Assets<Mesh>>().add(Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )))
+    };
+    let lods = SegmentLods { outer: empty_meshes(), inner: empty_meshes() };
+    //uvs are in texture tiles, so the texture has to repeat
+    let texture: Handle<Image> = world.resource::<AssetServer>().load_with_settings(
+        SLIDE_TEXTURE,
+        |settings: &mut ImageLoaderSettings| {
+            settings.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+                address_mode_u: ImageAddressMode::Repeat,
+                address_mode_v: ImageAddressMode::Repeat,
+                ..default()
+            });
+        },
+    );
+    let material = world
+        .resource_mut::<Assets<StandardMaterial>>()
+        .add(outer_material(Some(texture.clone())));
+    let inner_material = world
+        .resource_mut::<Assets<StandardMaterial>>()
+        .add(inner_material(Some(texture)));
+
+    let segment = world
+        .spawn((
+            Name::new(format!("Road Segment {idx}")),
+            PbrBundle {
+                mesh: lods.outer[0].clone(),
+                material,
+                ..default()
+            },
+            CustomMesh,
+        ))
+        .set_parent(slide)
+        .id();
+
+    let inner_wall = world
+        .spawn((
+            Name::new("Inner Wall"),
+            PbrBundle {
+                mesh: lods.inner[0].clone(),
+                material: inner_material,
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            InnerWall,
+        ))
+        .set_parent(segment)
+        .id();
+
+    world.entity_mut(segment).insert(RoadSegment {
+        pts_ids,
+        slide,
+        inner_wall,
+        ..default()
+    });
+    world.entity_mut(segment).insert(lods);
+
+    if let Some(mut s) = world.get_mut::<Slide>(slide) {
+        let idx = idx.min(s.segment_count());
+        s.segments.insert(idx, segment);
+    }
+
+    segment
+}
+
+//names follow the order of segments in the slide
+pub fn name_segments(world: &mut World, slide: Entity) {
+    let Some(segments) = world.get::<Slide>(slide).map(|s| s.segments.clone()) else { return; };
+    for (i, id) in segments.into_iter().enumerate() {
+        if let Some(mut name) = world.get_mut::<Name>(id) {
+            name.set(format!("Road Segment {i}"));
+        }
+    }
+}
+
+//direction is where the slide goes through this point, its length is the tangent length.
+//zero if the point is a tangent handle
+pub fn spawn_control_point(world: &mut World, slide: Entity, pos: Vec3, direction: Vec3) -> Entity {
+    let mesh = world.resource_mut::<Assets<Mesh>>().add(Sphere::new(1.));
+    let material = world
+        .resource_mut::<Assets<StandardMaterial>>()
+        .add(Color::srgba(1., 1., 1., 0.2));
+
+    let transform = control_point_transform(pos, direction);
+
+    world
+        .spawn((
+            Name::new("Control Point"),
+            PbrBundle {
+                mesh,
+                material,
+                transform,
+                ..default()
+            },
+            ControlPointDraggable {
+                tangent_len: if direction.length_squared() > f32::EPSILON { direction.length() } else { 1. },
+                ..default()
+            },
+        ))
+        .set_parent(slide)
+        .id()
+}
+
+//direction is where the slide goes through this point. zero if the point is a tangent handle.
+//scale stays 1, the length of direction is the tangent length of ControlPointDraggable
+pub fn control_point_transform(pos: Vec3, direction: Vec3) -> Transform {
+    let mut transform = Transform::from_translation(pos);
+    if direction.length_squared() > f32::EPSILON {
+        //tangent handles are placed along local forward
+        transform = transform.looking_to(direction, Vec3::Y);
+    }
+
+    transform
+}
+
+//outside is painted
+pub fn outer_material(texture: Option<Handle<Image>>) -> StandardMaterial {
+    StandardMaterial {
+        base_color: Color::Srgba(AQUA),
+        base_color_texture: texture,
+        ..default()
+    }
+}
+
+//inside is wet
+pub fn inner_material(texture: Option<Handle<Image>>) -> StandardMaterial {
+    StandardMaterial {
+        base_color: Color::srgb(0.15, 0.45, 0.8),
+        base_color_texture: texture,
+        perceptual_roughness: 0.08,
+        reflectance: 0.8,
+        ..default()
+    }
+}
@@ -0,0 +1,297 @@
+use bevy::{
+    ecs::world::Command,
+    prelude::*,
+};
+use bevy_egui::EguiContexts;
+use super::{
+    curve_kind::CurveKind,
+    history::{EditorCommand, History, SlideState},
+    oriented_point::OrientedPoint,
+    selection::Selection,
+    slide::{append_segment, insert_segment, name_segments, spawn_control_point, Slide},
+    smoothstep, ControlPointDraggable, ControlPoints, RoadSegment,
+};
+
+//edits that change which segments a slide has. each one is a single undo step holding
+//the slide before and after it, see SlideState
+
+//new control point on a segment at t. the segment is cut in two with de Casteljau,
+//so the halves together keep its shape. only bezier segments can be cut that way, other kinds are refused
+pub struct SplitSegment {
+    pub segment: Entity,
+    pub t: f32,
+}
+
+impl Command for SplitSegment {
+    fn apply(self, world: &mut World) {
+        //cutting at an end would leave a zero long half
+        let t = self.t.clamp(0.01, 0.99);
+        let Some(rs) = world.get::<RoadSegment>(self.segment).cloned() else { return; };
+        if rs.curve_kind != CurveKind::Bezier {
+            warn!("SplitSegment: only bezier segments can be split, {:?} is {}", self.segment, rs.curve_kind.name());
+            return;
+        }
+        let slide = rs.slide;
+        let Some(idx) = world.get::<Slide>(slide).and_then(|s| s.segments.iter().position(|id| *id == self.segment)) else { return; };
+        let Some(before) = SlideState::capture(world, slide) else { return; };
+        let Some(pts) = control_points(world, &rs) else { return; };
+
+        let ([p0, a, ab, mid], [_, bc, c, p3]) = split_bezier(rs.transforms_to_positions(&pts), t);
+
+        let mid_pt = spawn_control_point(world, slide, mid, bc - mid);
+        let left_handle = spawn_control_point(world, slide, ab, Vec3::ZERO);
+        let right_handle = spawn_control_point(world, slide, bc, Vec3::ZERO);
+        if let Some(mut cp) = world.get_mut::<ControlPointDraggable>(mid_pt) {
+            cp.bank = rs.bank_at(t);
+        }
+        //up of the new point goes on from the frames of the segment, world up would twist it on steep parts
+        if let (Some(forward), Some(mut trm)) = ((bc - mid).try_normalize(), world.get_mut::<Transform>(mid_pt)) {
+            let frame = OrientedPoint::from_forward_up(mid, forward, rs.get_rmf_oriented_points(&[t])[0].up());
+            trm.look_to(forward, frame.up());
+        }
+        for (pt_id, pos) in [(rs.pts_ids[1], a), (rs.pts_ids[2], c)] {
+            if let Some(mut trm) = world.get_mut::<Transform>(pt_id) {
+                trm.translation = pos;
+            }
+        }
+
+        //radius and profile blend ease along the segment like in ring_profile
+        let k = smoothstep(t);
+        let mid_radius = rs.radius[0] + (rs.radius[1] - rs.radius[0]) * k;
+        let mid_blend = rs.blend[0] + (rs.blend[1] - rs.blend[0]) * k;
+        let right = insert_segment(world, slide, idx + 1, [mid_pt, right_handle, rs.pts_ids[2], rs.pts_ids[3]]);
+        if let Some(mut left_rs) = world.get_mut::<RoadSegment>(self.segment) {
+            left_rs.pts_ids[2] = left_handle;
+            left_rs.pts_ids[3] = mid_pt;
+            left_rs.radius[1] = mid_radius;
+            left_rs.blend[1] = mid_blend;
+            left_rs.handle_lens = [Some(p0.distance(a)), Some(mid.distance(ab))];
+        }
+        if let Some(mut right_rs) = world.get_mut::<RoadSegment>(right) {
+            right_rs.curve_kind = rs.curve_kind;
+            right_rs.weights = rs.weights;
+            //right half goes on with the blend from where the left one stops
+            right_rs.profile = rs.profile.clone();
+            right_rs.end_profile = rs.end_profile.clone();
+            right_rs.blend = [mid_blend, rs.blend[1]];
+            right_rs.radius = [mid_radius, rs.radius[1]];
+            right_rs.handle_lens = [Some(mid.distance(bc)), Some(c.distance(p3))];
+        }
+        copy_color(world, self.segment, right);
+        if let Some(right_inner) = world.get::<RoadSegment>(right).map(|r| r.inner_wall) {
+            copy_color(world, rs.inner_wall, right_inner);
+        }
+
+        finish(world, "Insert point", slide, before, Some(mid_pt));
+    }
+}
+
+//de Casteljau: cubic bezier points -> points of the part before t and of the part after it
+fn split_bezier([p0, p1, p2, p3]: [Vec3; 4], t: f32) -> ([Vec3; 4], [Vec3; 4]) {
+    let (a, b, c) = (p0.lerp(p1, t), p1.lerp(p2, t), p2.lerp(p3, t));
+    let (ab, bc) = (a.lerp(b, t), b.lerp(c, t));
+    let mid = ab.lerp(bc, t);
+    ([p0, a, ab, mid], [mid, bc, c, p3])
+}
+
+//removes an end point. a joint merges the two segments around it into one,
+//an end of the slide takes its segment with it. tangent handles can not be deleted
+pub struct DeletePoint {
+    pub point: Entity,
+}
+
+impl Command for DeletePoint {
+    fn apply(self, world: &mut World) {
+        let Some(slide) = world.get::<Parent>(self.point).map(|p| p.get()) else { return; };
+        let Some(before) = SlideState::capture(world, slide) else { return; };
+
+        let ending = before.segments.iter().position(|(_, rs)| rs.end_pt_id() == self.point);
+        let starting = before.segments.iter().position(|(_, rs)| rs.start_pt_id() == self.point);
+        let mut after = before.clone();
+        match (ending, starting) {
+            (Some(k), Some(next)) => {
+                let (_, next_rs) = after.segments.remove(next);
+                merge_segments(&mut after.segments[k].1, &next_rs, &before);
+            }
+            (None, None) => {
+                warn!("DeletePoint: {:?} is not an end point of a segment", self.point);
+                return;
+            }
+            _ if before.segments.len() < 2 => {
+                warn!("DeletePoint: slide {:?} has only one segment", slide);
+                return;
+            }
+            (Some(k), None) | (None, Some(k)) => {
+                after.segments.remove(k);
+            }
+        }
+        after.retain_used_points();
+        after.restore(world, slide, &before);
+
+        finish(world, "Delete point", slide, before, None);
+    }
+}
+
+//segment takes the place of itself and the next one. handles are stretched by how much longer
+//the merged segment is than each of the two, so the curve leaves the ends as fast as before
+fn merge_segments(rs: &mut RoadSegment, next: &RoadSegment, state: &SlideState) {
+    let tangent_len_of = |id: Entity| state.points.iter().find(|(pt_id, ..)| *pt_id == id).map(|(_, _, cp)| cp.tangent_len);
+    let (Some(start_len), Some(end_len)) = (tangent_len_of(rs.start_pt_id()), tangent_len_of(next.end_pt_id())) else { return; };
+
+    let (len, next_len) = (rs.get_len(), next.get_len());
+    let total = len + next_len;
+    let stretch = |handle_len: f32, part: f32| if part > f32::EPSILON { handle_len * total / part } else { handle_len };
+    rs.handle_lens = [
+        Some(stretch(rs.handle_len(0, start_len), len)),
+        Some(stretch(next.handle_len(1, end_len), next_len)),
+    ];
+
+    rs.pts_ids[2] = next.pts_ids[2];
+    rs.pts_ids[3] = next.pts_ids[3];
+    rs.radius[1] = next.radius[1];
+    rs.blend = [
+        if rs.end_profile.is_some() { rs.blend[0] } else { 0. },
+        if next.end_profile.is_some() { next.blend[1] } else { 1. },
+    ];
+    rs.end_profile = next
+        .end_profile
+        .clone()
+        .or_else(|| (next.profile != rs.profile).then(|| next.profile.clone()));
+}
+
+//adds a segment straight ahead of the start or the end of the slide, as long as the segment there
+pub struct ExtendSlide {
+    pub slide: Entity,
+    pub at_start: bool,
+}
+
+impl Command for ExtendSlide {
+    fn apply(self, world: &mut World) {
+        let Some(before) = SlideState::capture(world, self.slide) else { return; };
+        let end_segment = if self.at_start { before.segments.first() } else { before.segments.last() };
+        let Some((_, rs)) = end_segment else { return; };
+        let (Some(start_trm), Some(end_trm)) = (
+            world.get::<Transform>(rs.start_pt_id()).copied(),
+            world.get::<Transform>(rs.end_pt_id()).copied(),
+        ) else { return; };
+        let len = start_trm.translation.distance(end_trm.translation).max(1.);
+        let start_tangent_len = world.get::<ControlPointDraggable>(rs.start_pt_id()).map_or(1., |cp| cp.tangent_len);
+
+        let new_point = if self.at_start {
+            let dir = *start_trm.forward();
+            let start = start_trm.translation - dir * len;
+            let start_pt = spawn_control_point(world, self.slide, start, dir * (len / 3.));
+            let start_handle = spawn_control_point(world, self.slide, start + dir * (len / 3.), Vec3::ZERO);
+            let end_handle = spawn_control_point(
+                world,
+                self.slide,
+                start_trm.translation - dir * start_tangent_len,
+                Vec3::ZERO,
+            );
+            let segment = insert_segment(world, self.slide, 0, [start_pt, start_handle, end_handle, rs.start_pt_id()]);
+            //the old first segment goes on from the new one, so the new one looks like its start
+            let joint = rs.joint(0);
+            if let Some(mut new_rs) = world.get_mut::<RoadSegment>(segment) {
+                joint.apply_to(&mut new_rs);
+                new_rs.radius[1] = joint.radius;
+                new_rs.blend[1] = joint.blend;
+            }
+            Some(start_pt)
+        } else {
+            let dir = *end_trm.forward();
+            let end = end_trm.translation + dir * len;
+            let radius = rs.radius[1];
+            //start continues the old last segment, the end keeps its radius too
+            append_segment(world, self.slide, end - dir * (len / 3.), end)
+                .and_then(|segment| world.get_mut::<RoadSegment>(segment))
+                .map(|mut new_rs| {
+                    new_rs.radius = [radius; 2];
+                    new_rs.end_pt_id()
+                })
+        };
+
+        finish(world, "Extend slide", self.slide, before, new_point);
+    }
+}
+
+fn control_points(world: &World, rs: &RoadSegment) -> Option<ControlPoints> {
+    let [a, b, c, d] = rs.pts_ids.map(|id| world.get::<Transform>(id).copied());
+    let [start, end] = [rs.start_pt_id(), rs.end_pt_id()].map(|id| world.get::<ControlPointDraggable>(id).map(|cp| cp.tangent_len));
+    Some(ControlPoints { trms: [a?, b?, c?, d?], tangent_lens: [start?, end?] })
+}
+
+fn copy_color(world: &mut World, from: Entity, to: Entity) {
+    let (Some(from), Some(to)) = (
+        world.get::<Handle<StandardMaterial>>(from).cloned(),
+        world.get::<Handle<StandardMaterial>>(to).cloned(),
+    ) else { return; };
+    let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+    let Some(color) = materials.get(&from).map(|m| m.base_color) else { return; };
+    if let Some(material) = materials.get_mut(&to) {
+        material.base_color = color;
+    }
+}
+
+//records the edit and selects the point it made
+fn finish(world: &mut World, name: &'static str, slide: Entity, before: SlideState, select: Option<Entity>) {
+    name_segments(world, slide);
+    let Some(after) = SlideState::capture(world, slide) else { return; };
+    let mut history = world.resource_mut::<History>();
+    history.record(EditorCommand::Restructure { name, slide, before, after });
+    history.seal();
+
+    let mut selection = world.resource_mut::<Selection>();
+    selection.clear();
+    if let Some(point) = select {
+        selection.add_point(point);
+    }
+}
+
+//delete removes the selected end point
+pub fn delete_point_hotkey(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    keys: Res<ButtonInput<KeyCode>>,
+    selection: Res<Selection>,
+) {
+    if contexts.ctx_mut().wants_keyboard_input() { return; }
+    if !keys.just_pressed(KeyCode::Delete) { return; }
+
+    if let Some(point) = selection.primary_point() {
+        commands.add(DeletePoint { point });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::cubic_splines::{CubicBezier, CubicGenerator};
+    use super::*;
+
+    #[test]
+    fn split_halves_follow_the_curve() {
+        let pts = [Vec3::ZERO, Vec3::new(1., 2., 0.), Vec3::new(3., -1., 1.), Vec3::new(4., 0., 0.)];
+        let t = 0.3;
+        let (left, right) = split_bezier(pts, t);
+        let curve = CubicBezier::new([pts]).to_curve();
+        let left_curve = CubicBezier::new([left]).to_curve();
+        let right_curve = CubicBezier::new([right]).to_curve();
+
+        for i in 0..=10 {
+            let u = i as f32 / 10.;
+            assert!(left_curve.position(u).distance(curve.position(u * t)) < 1e-5);
+            assert!(right_curve.position(u).distance(curve.position(t + u * (1. - t))) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn split_halves_meet_on_the_curve() {
+        let pts = [Vec3::ZERO, Vec3::X, Vec3::new(2., 1., 0.), Vec3::new(3., 1., 0.)];
+        let (left, right) = split_bezier(pts, 0.5);
+        assert_eq!(left[0], pts[0]);
+        assert_eq!(right[3], pts[3]);
+        assert_eq!(left[3], right[0]);
+        //tangents on both sides of the cut point the same way
+        let (into, out) = ((left[3] - left[2]).normalize(), (right[1] - right[0]).normalize());
+        assert!(into.dot(out) > 1. - 1e-5);
+    }
+}
@@ -0,0 +1,116 @@
+use bevy::{color::palettes::css::{ORANGE, YELLOW}, prelude::*};
+use crate::my_ui::SnapSettings;
+use super::{drag::DragConstraint, slide::Slide, RoadSegment};
+
+//what a dragged point can snap to besides the grid
+#[derive(Default)]
+pub struct SnapTargets {
+    //end point the dragged tangent handle belongs to, handle direction is snapped around it
+    pub handle_anchor: Option<Vec3>,
+    //free ends of other slides, only when the dragged point is a free end itself
+    pub free_ends: Vec<Vec3>,
+}
+
+impl SnapTargets {
+    pub fn find(
+        point: Entity,
+        slides: &Query<&Slide>,
+        road_segments: &Query<&RoadSegment>,
+        position: impl Fn(Entity) -> Option<Vec3>,
+    ) -> Self {
+        let mut targets = SnapTargets::default();
+
+        targets.handle_anchor = road_segments
+            .iter()
+            .find_map(|rs| rs.handle_anchor(point))
+            .and_then(|(anchor, _)| position(anchor));
+
+        let ends: Vec<[Entity; 2]> = slides.iter().filter_map(|s| slide_ends(s, road_segments)).collect();
+        if ends.iter().any(|e| e.contains(&point)) {
+            targets.free_ends = ends
+                .iter()
+                .filter(|e| !e.contains(&point))
+                .flatten()
+                .filter_map(|id| position(*id))
+                .collect();
+        }
+
+        targets
+    }
+}
+
+//start point of the first segment and end point of the last one
+fn slide_ends(slide: &Slide, road_segments: &Query<&RoadSegment>) -> Option<[Entity; 2]> {
+    let first = road_segments.get(*slide.segments.first()?).ok()?;
+    let last = road_segments.get(slide.last_segment()?).ok()?;
+    Some([first.start_pt_id(), last.end_pt_id()])
+}
+
+//magnet wins over angle, angle over grid and height.
+//grid and height snapping keep the point on the drag axis or the ground plane, others may leave them
+pub fn snap(
+    target: Vec3,
+    constraint: DragConstraint,
+    settings: &SnapSettings,
+    targets: &SnapTargets,
+    gizmos: &mut Gizmos,
+) -> Vec3 {
+    if settings.magnet {
+        let nearest = targets.free_ends
+            .iter()
+            .map(|end| (*end, end.distance(target)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        for end in targets.free_ends.iter() {
+            gizmos.sphere(*end, Quat::IDENTITY, settings.magnet_radius, ORANGE.with_alpha(0.3)).resolution(12);
+        }
+        if let Some((end, _)) = nearest.filter(|(_, dist)| *dist <= settings.magnet_radius) {
+            gizmos.sphere(end, Quat::IDENTITY, settings.magnet_radius, YELLOW).resolution(12);
+            return end;
+        }
+    }
+
+    if let Some(anchor) = targets.handle_anchor.filter(|_| settings.angle) {
+        let snapped = anchor + snap_direction(target - anchor, settings.angle_step.to_radians());
+        gizmos.line(anchor, anchor + (snapped - anchor) * 2., YELLOW);
+        return snapped;
+    }
+
+    let mut snapped = target;
+    if settings.grid {
+        snapped.x = round_to(snapped.x, settings.grid_size);
+        snapped.z = round_to(snapped.z, settings.grid_size);
+    }
+    if settings.height {
+        snapped.y = round_to(snapped.y, settings.height_step);
+    }
+    let snapped = match constraint {
+        DragConstraint::Axis(axis) => target + axis * (snapped - target).dot(*axis),
+        DragConstraint::Plane(normal) if normal == Dir3::Y => snapped.with_y(target.y),
+        DragConstraint::Plane(_) => snapped,
+    };
+
+    if snapped != target {
+        gizmos.sphere(snapped, Quat::IDENTITY, 0.3, YELLOW).resolution(8);
+        if settings.grid {
+            let cell = Vec2::splat(settings.grid_size);
+            gizmos.grid(snapped, Quat::from_rotation_x(std::f32::consts::FRAC_PI_2), UVec2::splat(4), cell, YELLOW.with_alpha(0.3));
+        }
+    }
+
+    snapped
+}
+
+fn round_to(v: f32, step: f32) -> f32 {
+    if step <= 0. { return v; }
+    (v / step).round() * step
+}
+
+//yaw around world up and pitch from the ground rounded to step radians, length is kept
+fn snap_direction(dir: Vec3, step: f32) -> Vec3 {
+    let len = dir.length();
+    if len < f32::EPSILON || step <= 0. { return dir; }
+
+    let yaw = round_to(dir.x.atan2(dir.z), step);
+    let pitch = round_to((dir.y / len).clamp(-1., 1.).asin(), step);
+    Vec3::new(yaw.sin() * pitch.cos(), pitch.sin(), yaw.cos() * pitch.cos()) * len
+}
@@ -0,0 +1,184 @@
+use std::f32::consts::{PI, TAU};
+use bevy::{
+    color::palettes::css::{GRAY, LIME, RED, YELLOW},
+    prelude::*,
+};
+use bevy_egui::{egui, EguiContexts};
+use super::{
+    history::{EditorCommand, History, PointMove},
+    selection::Selection,
+    ControlPointDraggable, RoadSegment,
+};
+
+//ring size on screen, part of the distance to the camera
+const RING_SCALE: f32 = 0.08;
+//how close to a ring the cursor has to be to grab it, part of the ring radius
+const RING_GRAB: f32 = 0.15;
+
+//rings around the selected end point aim its tangent
+#[derive(Clone, Copy, PartialEq)]
+pub enum Ring {
+    //horizontal, turns the tangent around world up
+    Yaw,
+    //upright through the tangent, tilts it up and down
+    Pitch,
+}
+
+impl Ring {
+    const ALL: [Ring; 2] = [Ring::Yaw, Ring::Pitch];
+
+    fn normal(self, trm: &Transform) -> Option<Dir3> {
+        match self {
+            Ring::Yaw => Some(Dir3::Y),
+            Ring::Pitch => Dir3::new(trm.forward().cross(Vec3::Y)).ok(),
+        }
+    }
+
+    fn color(self) -> Srgba {
+        match self {
+            Ring::Yaw => LIME,
+            Ring::Pitch => RED,
+        }
+    }
+
+    //angle of the cursor around the ring center and its distance from it
+    fn cursor_angle(self, trm: &Transform, ray: Ray3d) -> Option<(f32, f32)> {
+        let normal = self.normal(trm)?;
+        let d = ray.intersect_plane(trm.translation, InfinitePlane3d::new(normal))?;
+        let offset = ray.get_point(d) - trm.translation;
+        let reference = normal.any_orthonormal_vector();
+        let angle = normal.dot(reference.cross(offset)).atan2(reference.dot(offset));
+        Some((angle, offset.length()))
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct TangentGizmo {
+    //ring under the cursor, clicks on it do not go to selection
+    pub hovered: Option<Ring>,
+    drag: Option<RingDrag>,
+}
+
+struct RingDrag {
+    ring: Ring,
+    point: Entity,
+    before: Transform,
+    last_angle: f32,
+}
+
+//selected end point, the one rings are shown around
+fn ring_point(selection: &Selection, road_segments: &Query<&RoadSegment>) -> Option<Entity> {
+    let point = selection.primary_point()?;
+    road_segments.iter().any(|rs| rs.is_end_point(point)).then_some(point)
+}
+
+//turning the point by a ring is one undo step
+pub fn tangent_rings(
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    windows: Query<&Window>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    mut contexts: EguiContexts,
+    selection: Res<Selection>,
+    mut gizmo: ResMut<TangentGizmo>,
+    mut history: ResMut<History>,
+    road_segments: Query<&RoadSegment>,
+    mut transforms: Query<&mut Transform, With<ControlPointDraggable>>,
+    mut gizmos: Gizmos,
+) {
+    let ring_trm = ring_point(&selection, &road_segments).and_then(|p| Some((p, transforms.get_mut(p).ok()?)));
+    let Some((point, mut trm)) = ring_trm else {
+        gizmo.hovered = None;
+        gizmo.drag = None;
+        return;
+    };
+
+    let (camera, camera_transform) = cameras.single();
+    let radius = camera_transform.translation().distance(trm.translation) * RING_SCALE;
+    let ray = windows
+        .single()
+        .cursor_position()
+        .and_then(|pos| camera.viewport_to_world(camera_transform, pos));
+    let over_ui = contexts.ctx_mut().is_pointer_over_area();
+
+    let hovered = ray.filter(|_| !over_ui && gizmo.drag.is_none()).and_then(|ray| {
+        Ring::ALL.into_iter().find(|ring| {
+            ring.cursor_angle(&trm, ray).is_some_and(|(_, dist)| (dist - radius).abs() < radius * RING_GRAB)
+        })
+    });
+    if gizmo.hovered != hovered {
+        gizmo.hovered = hovered;
+    }
+
+    for ring in Ring::ALL {
+        let Some(normal) = ring.normal(&trm) else { continue; };
+        let active = gizmo.hovered == Some(ring) || gizmo.drag.as_ref().is_some_and(|d| d.ring == ring);
+        let color = if active { YELLOW } else { ring.color() };
+        gizmos.circle(trm.translation, normal, radius, color).resolution(48);
+    }
+
+    if buttons.just_pressed(MouseButton::Left) {
+        if let (Some(ring), Some(ray)) = (hovered, ray) {
+            if let Some((angle, _)) = ring.cursor_angle(&trm, ray) {
+                gizmo.drag = Some(RingDrag { ring, point, before: *trm, last_angle: angle });
+            }
+        }
+    }
+
+    if buttons.just_released(MouseButton::Left) {
+        if let Some(drag) = gizmo.drag.take() {
+            if drag.before != *trm {
+                history.record(EditorCommand::MovePoints(vec![PointMove { point: drag.point, before: drag.before, after: *trm }]));
+                history.seal();
+            }
+        }
+        return;
+    }
+
+    let (Some(drag), Some(ray)) = (gizmo.drag.as_mut(), ray) else { return; };
+    if drag.point != point { return; }
+    let Some(normal) = drag.ring.normal(&trm) else { return; };
+    let Some((angle, _)) = drag.ring.cursor_angle(&trm, ray) else { return; };
+
+    //shortest way, angles wrap at pi
+    let delta = (angle - drag.last_angle + PI).rem_euclid(TAU) - PI;
+    drag.last_angle = angle;
+    let forward = Quat::from_axis_angle(*normal, delta) * *trm.forward();
+    //straight up or down has no upright ring to come back with
+    if delta != 0. && forward.cross(Vec3::Y).length_squared() > 1e-4 {
+        trm.look_to(forward, Vec3::Y);
+    }
+}
+
+//lines from end points to their tangent handles and the tangent length next to the handles
+//of the selected points
+pub fn draw_tangent_handles(
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut contexts: EguiContexts,
+    selection: Res<Selection>,
+    road_segments: Query<&RoadSegment>,
+    transforms: Query<&Transform, With<ControlPointDraggable>>,
+    mut gizmos: Gizmos,
+) {
+    let (camera, camera_transform) = cameras.single();
+    let painter = contexts
+        .ctx_mut()
+        .layer_painter(egui::LayerId::new(egui::Order::Background, egui::Id::new("tangent_lengths")));
+
+    for rs in road_segments.iter() {
+        for handle in [rs.pts_ids[1], rs.pts_ids[2]] {
+            let Some((anchor, _)) = rs.handle_anchor(handle) else { continue; };
+            let (Ok(handle_trm), Ok(anchor_trm)) = (transforms.get(handle), transforms.get(anchor)) else { continue; };
+            gizmos.line(anchor_trm.translation, handle_trm.translation, GRAY);
+
+            if !selection.is_selected(handle) && !selection.is_selected(anchor) { continue; }
+            let Some(pos) = camera.world_to_viewport(camera_transform, handle_trm.translation) else { continue; };
+            painter.text(
+                egui::pos2(pos.x + 8., pos.y),
+                egui::Align2::LEFT_CENTER,
+                format!("{:.2} m", anchor_trm.translation.distance(handle_trm.translation)),
+                egui::FontId::proportional(14.),
+                egui::Color32::WHITE,
+            );
+        }
+    }
+}
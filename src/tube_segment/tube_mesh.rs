@@ -0,0 +1,364 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology},
+        render_asset::RenderAssetUsages,
+    },
+};
+use crate::my_ui::RingSpacing;
+use super::{oriented_point::OrientedPoint, profile_shape::ProfileShape, RoadSegment};
+
+//levels of detail every segment is built with, each next one has about half of the rings
+pub const LOD_COUNT: usize = 3;
+
+//everything meshes of one segment are built from. owned, so they can be built on another thread
+pub struct SegmentMeshInput {
+    pub segment: RoadSegment,
+    //start and end profiles, see RoadSegment::ring_profiles
+    pub ring_profiles: (ProfileShape, ProfileShape),
+    pub sections_amnt: usize,
+    pub ring_spacing: RingSpacing,
+    //for adaptive ring spacing
+    pub curve_tolerance: f32,
+    pub wall_thickness: f32,
+    pub uv_tile_size: f32,
+    //cap the start and the end, only ends of a slide get caps
+    pub caps: [bool; 2],
+}
+
+pub struct SegmentMeshes {
+    pub outer: Mesh,
+    //None when the tube has no wall thickness
+    pub inner: Option<Mesh>,
+}
+
+impl SegmentMeshInput {
+    //meshes of every level of detail, from the most detailed one
+    pub fn build(self) -> Vec<SegmentMeshes> {
+        (0..LOD_COUNT).map(|lod| self.build_lod(lod)).collect()
+    }
+
+    pub fn build_lod(&self, lod: usize) -> SegmentMeshes {
+        let rs = &self.segment;
+        let ring_ts = match self.ring_spacing {
+            //error grows with square of ring distance, so 4 times the tolerance is about half of the rings
+            RingSpacing::Adaptive => rs.adaptive_ring_ts(self.curve_tolerance * 4_f32.powi(lod as i32), self.probe_radius()),
+            spacing => rs.ring_ts(((self.sections_amnt - 1) >> lod).max(1) + 1, spacing),
+        };
+        let ring_ops = rs.get_banked_oriented_points(&ring_ts);
+        //all ring profiles share topology of the start one
+        let ring_shapes: Vec<ProfileShape> = ring_ts.iter().map(|&t| rs.ring_profile(&self.ring_profiles, t)).collect();
+        let ring_vs: Vec<f32> = ring_ts.iter().map(|&t| rs.start_distance + rs.arc_lengths.distance_at_t(t)).collect();
+
+        let mut outer = TubeMeshBuffers::default();
+
+        if self.wall_thickness <= 0. {
+            //single surface looking out of the profile
+            outer.push_wall(&ring_ops, &ring_shapes, &ring_vs, false);
+
+            return SegmentMeshes { outer: outer.into_mesh(self.uv_tile_size), inner: None };
+        }
+
+        //wall grows outwards so the riding surface keeps the profile size
+        let outer_shapes: Vec<ProfileShape> = ring_shapes.iter().map(|s| s.offset(self.wall_thickness)).collect();
+        outer.push_wall(&ring_ops, &outer_shapes, &ring_vs, false);
+        outer.push_lips(&ring_ops, &outer_shapes, &ring_shapes, &ring_vs);
+
+        let last = ring_ops.len() - 1;
+        if self.caps[0] {
+            outer.push_cap(ring_ops[0], &outer_shapes[0], &ring_shapes[0], true);
+        }
+        if self.caps[1] {
+            outer.push_cap(ring_ops[last], &outer_shapes[last], &ring_shapes[last], false);
+        }
+
+        let mut inner = TubeMeshBuffers::default();
+        inner.push_wall(&ring_ops, &ring_shapes, &ring_vs, true);
+
+        SegmentMeshes {
+            outer: outer.into_mesh(self.uv_tile_size),
+            inner: Some(inner.into_mesh(self.uv_tile_size)),
+        }
+    }
+
+    //farthest profile point from the curve, without radius scale
+    fn probe_radius(&self) -> f32 {
+        let (start, end) = &self.ring_profiles;
+        start.vertices
+            .iter()
+            .chain(end.vertices.iter())
+            .map(|v| v.point.length())
+            .fold(0., f32::max)
+    }
+}
+
+//riding surface ring at the start (0) or the end (1) of a segment, in world space.
+//walls, lips and caps are all built from it, so a joint is closed when both its rings are the same
+pub fn end_ring(segment: &RoadSegment, ring_profiles: &(ProfileShape, ProfileShape), side: usize) -> Vec<Vec3> {
+    let t = side as f32;
+    let op = segment.get_banked_oriented_points(&[t])[0];
+    segment.ring_profile(ring_profiles, t)
+        .vertices
+        .iter()
+        .map(|v| op.local_to_world_pos(v.point))
+        .collect()
+}
+
+//end ring of a segment and start ring of the next one meet vertex to vertex.
+//profiles resampled to different topologies or a kink in the curve leave a gap
+pub fn rings_meet(end: &[Vec3], start: &[Vec3]) -> bool {
+    const TOLERANCE: f32 = 1e-4;
+    end.len() == start.len() && end.iter().zip(start).all(|(a, b)| a.distance(*b) <= TOLERANCE)
+}
+
+//vertices and triangles of one surface of a segment, walls and caps are pushed into it one by one
+#[derive(Default)]
+pub struct TubeMeshBuffers {
+    pub verts: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    //in world units, scaled by texture tile size when turned into a mesh
+    pub uvs: Vec<Vec2>,
+    pub tri_indices: Vec<u32>,
+}
+
+impl TubeMeshBuffers {
+    //
+    //  A                   B
+    //  .___________________.
+    //  |    ring next      |
+    //  |                /  |
+    //  |             /     |
+    //  |          /        |
+    //  |       /           |
+    //  |    /              |
+    //  | /                 |
+    //  .___________________.
+    //  A    ring curr      B
+    //
+    // should be counter-clockwise in bevy
+    //
+    //one ring of shape per oriented point, all shapes with the same topology.
+    //ring_vs: distance of every ring from the start of the slide.
+    //flipped wall looks inside the profile
+    pub fn push_wall(&mut self, ring_ops: &[OrientedPoint], ring_shapes: &[ProfileShape], ring_vs: &[f32], flip: bool) {
+        let Some(shape2d) = ring_shapes.first() else { return; };
+        let base = self.verts.len();
+
+        for ((op, shape), &v_dist) in ring_ops.iter().zip(ring_shapes.iter()).zip(ring_vs.iter()) {
+            //u of a vertex is a fraction of the outline, in world units it is the distance along it
+            let u_span = shape.calc_u_span();
+            for v in shape.vertices.iter() {
+                let normal = op.local_to_world_vec(v.normal);
+                self.verts.push(op.local_to_world_pos(v.point));
+                self.normals.push(if flip { -normal } else { normal });
+                self.uvs.push(Vec2::new(v.u * u_span, v_dist));
+            }
+        }
+
+        for ring in 0..ring_shapes.len().saturating_sub(1) {
+            let root_idx = base + ring * shape2d.vertex_count();
+            let root_idx_next = base + (ring + 1) * shape2d.vertex_count();
+
+            for line in shape2d.line_indices.chunks(2) {
+                let curr_a = root_idx + line[0];
+                let curr_b = root_idx + line[1];
+                let next_a = root_idx_next + line[0];
+                let next_b = root_idx_next + line[1];
+
+                self.push_quad([curr_a, curr_b, next_b, next_a], flip);
+            }
+        }
+    }
+
+    //flat ring between outer and inner outlines. looks backwards on the start of the slide, forwards on its end
+    pub fn push_cap(&mut self, op: OrientedPoint, outer: &ProfileShape, inner: &ProfileShape, is_start: bool) {
+        let base = self.verts.len();
+        let normal = op.rot * if is_start { Vec3::NEG_Z } else { Vec3::Z };
+        for shape in [outer, inner] {
+            for v in shape.vertices.iter() {
+                self.verts.push(op.local_to_world_pos(v.point));
+                self.normals.push(normal);
+                //flat projection of the profile plane
+                self.uvs.push(v.point);
+            }
+        }
+
+        let inner_base = base + outer.vertex_count();
+        for line in outer.line_indices.chunks(2) {
+            self.push_quad(
+                [base + line[0], base + line[1], inner_base + line[1], inner_base + line[0]],
+                is_start,
+            );
+        }
+    }
+
+    //strips along the segment closing the wall where an open profile ends
+    pub fn push_lips(&mut self, ring_ops: &[OrientedPoint], outer: &[ProfileShape], inner: &[ProfileShape], ring_vs: &[f32]) {
+        let Some(shape2d) = inner.first() else { return; };
+        let (starts, ends) = shape2d.open_ends();
+
+        for (vertices, flip) in [(starts, false), (ends, true)] {
+            for i in vertices {
+                let base = self.verts.len();
+                for (((op, o), n), &v_dist) in ring_ops.iter().zip(outer.iter()).zip(inner.iter()).zip(ring_vs.iter()) {
+                    let (o, n) = (o.vertices[i].point, n.vertices[i].point);
+                    //lip looks back along the outline on its start and forward on its end
+                    let out = (o - n).normalize_or_zero();
+                    let normal = op.local_to_world_vec(if flip { Vec2::new(-out.y, out.x) } else { Vec2::new(out.y, -out.x) });
+                    self.verts.push(op.local_to_world_pos(o));
+                    self.verts.push(op.local_to_world_pos(n));
+                    self.normals.extend([normal, normal]);
+                    //across the lip is the wall thickness
+                    self.uvs.push(Vec2::new(0., v_dist));
+                    self.uvs.push(Vec2::new(o.distance(n), v_dist));
+                }
+
+                for ring in 0..ring_ops.len().saturating_sub(1) {
+                    let (outer_curr, inner_curr) = (base + ring * 2, base + ring * 2 + 1);
+                    let (outer_next, inner_next) = (outer_curr + 2, inner_curr + 2);
+                    self.push_quad([outer_curr, outer_next, inner_next, inner_curr], flip);
+                }
+            }
+        }
+    }
+
+    //uv_tile_size: world units one texture repeat covers
+    pub fn into_mesh(self, uv_tile_size: f32) -> Mesh {
+        let uvs: Vec<Vec2> = self.uvs.iter().map(|uv| *uv / uv_tile_size.max(f32::EPSILON)).collect();
+
+        let mut mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.verts)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_indices(Indices::U32(self.tri_indices));
+
+        //for normal mapped materials
+        if let Err(e) = mesh.generate_tangents() {
+            warn!("slide mesh has no tangents: {e}");
+        }
+
+        mesh
+    }
+
+    //counter-clockwise quad a b c d as two triangles
+    fn push_quad(&mut self, [a, b, c, d]: [usize; 4], flip: bool) {
+        let tris = if flip { [a, c, b, a, d, c] } else { [a, b, c, a, c, d] };
+        self.tri_indices.extend(tris.map(|i| i as u32));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use bevy::render::mesh::VertexAttributeValues;
+    use crate::tube_segment::{profile_shape::ProfileKind, ControlPoints};
+    use super::*;
+
+    //bezier from one end point to the other, up continuing the previous segment
+    fn segment(mut rs: RoadSegment, [from, to]: [Vec3; 2], up: Vec3) -> RoadSegment {
+        let pts = ControlPoints {
+            trms: [
+                Transform::from_translation(from).looking_to(Vec3::NEG_Z, Vec3::Y),
+                Transform::default(),
+                Transform::default(),
+                Transform::from_translation(to).looking_to(Vec3::NEG_Z, Vec3::Y),
+            ],
+            tangent_lens: [3., 3.],
+        };
+        rs.store_curve(rs.curve_input(&pts));
+        rs.start_up = rs.start_up_from(up);
+        rs
+    }
+
+    fn mesh_input(rs: RoadSegment, caps: [bool; 2]) -> SegmentMeshInput {
+        SegmentMeshInput {
+            ring_profiles: rs.ring_profiles(&Assets::default()).unwrap(),
+            segment: rs,
+            sections_amnt: 8,
+            ring_spacing: RingSpacing::ArcLength,
+            curve_tolerance: 0.02,
+            wall_thickness: 0.1,
+            uv_tile_size: 1.,
+            caps,
+        }
+    }
+
+    fn capped_segment(profile: ProfileKind) -> SegmentMeshes {
+        let rs = segment(RoadSegment { profile, ..default() }, [Vec3::ZERO, Vec3::new(5., 1., -10.)], Vec3::Y);
+        mesh_input(rs, [true, true]).build_lod(0)
+    }
+
+    //two segments of one slide, the second one starts with the circle the first one ends with
+    fn joined_segments(second: RoadSegment) -> [SegmentMeshInput; 2] {
+        let first = segment(RoadSegment::default(), [Vec3::ZERO, Vec3::new(5., 1., -10.)], Vec3::Y);
+        let second = segment(second, [Vec3::new(5., 1., -10.), Vec3::new(3., -2., -22.)], first.end_up());
+        [mesh_input(first, [true, false]), mesh_input(second, [false, true])]
+    }
+
+    fn joint_rings([first, second]: &[SegmentMeshInput; 2]) -> (Vec<Vec3>, Vec<Vec3>) {
+        (
+            end_ring(&first.segment, &first.ring_profiles, 1),
+            end_ring(&second.segment, &second.ring_profiles, 0),
+        )
+    }
+
+    //every edge, by the positions of its ends, is shared by exactly two triangles
+    fn is_closed(meshes: &[&Mesh]) -> bool {
+        let mut edges = HashMap::<[[i64; 3]; 2], usize>::new();
+        for mesh in meshes {
+            let Some(VertexAttributeValues::Float32x3(positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) else { return false; };
+            let key = |i: usize| positions[i].map(|c| (c * 1e4).round() as i64);
+            let indices: Vec<usize> = mesh.indices().unwrap().iter().collect();
+            for tri in indices.chunks_exact(3) {
+                for (a, b) in [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])] {
+                    let (a, b) = (key(a), key(b));
+                    *edges.entry(if a < b { [a, b] } else { [b, a] }).or_default() += 1;
+                }
+            }
+        }
+        !edges.is_empty() && edges.values().all(|&n| n == 2)
+    }
+
+    #[test]
+    fn closed_profile_with_caps_is_closed() {
+        let built = capped_segment(ProfileKind::Circle { sides: 8 });
+        assert!(is_closed(&[&built.outer, built.inner.as_ref().unwrap()]));
+    }
+
+    #[test]
+    fn open_profile_with_lips_and_caps_is_closed() {
+        let built = capped_segment(ProfileKind::HalfPipe { sides: 6, opening: 120. });
+        assert!(is_closed(&[&built.outer, built.inner.as_ref().unwrap()]));
+    }
+
+    #[test]
+    fn joint_of_same_profiles_is_closed() {
+        let inputs = joined_segments(RoadSegment::default());
+        let (end, start) = joint_rings(&inputs);
+        assert!(rings_meet(&end, &start));
+
+        let [a, b] = inputs.map(|input| input.build_lod(0));
+        assert!(is_closed(&[&a.outer, a.inner.as_ref().unwrap(), &b.outer, b.inner.as_ref().unwrap()]));
+    }
+
+    #[test]
+    fn joint_into_other_profile_is_open() {
+        //starts as the circle, but resampled to share topology with the flume it blends into
+        let second = RoadSegment { end_profile: Some(ProfileKind::Flume { width: 1.5, height: 0.8 }), ..default() };
+        let inputs = joined_segments(second);
+        let (end, start) = joint_rings(&inputs);
+        assert!(!rings_meet(&end, &start));
+
+        let [a, b] = inputs.map(|input| input.build_lod(0));
+        assert!(!is_closed(&[&a.outer, a.inner.as_ref().unwrap(), &b.outer, b.inner.as_ref().unwrap()]));
+    }
+
+    #[test]
+    fn outer_wall_alone_is_open() {
+        let built = capped_segment(ProfileKind::Circle { sides: 8 });
+        assert!(!is_closed(&[&built.outer]));
+    }
+}